@@ -0,0 +1,20 @@
+-- Attestations issued in the background, see `src/jobs.rs`.
+CREATE TABLE verification_jobs (
+    id TEXT PRIMARY KEY NOT NULL,
+    address TEXT NOT NULL,
+    status TEXT NOT NULL CHECK (status IN ('pending', 'confirmed', 'failed')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    signature TEXT,
+    error TEXT,
+    -- Set while a worker is processing the job, cleared on startup.
+    claimed INTEGER NOT NULL DEFAULT 0,
+    -- Unix seconds.
+    next_attempt_at INTEGER NOT NULL,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+CREATE INDEX verification_jobs_due ON verification_jobs (status, next_attempt_at);
+-- A wallet has at most one pending job.
+CREATE UNIQUE INDEX verification_jobs_pending ON verification_jobs (address)
+WHERE status = 'pending';
@@ -0,0 +1,35 @@
+-- Issuance ledger, see `src/ledger.rs`.
+CREATE TABLE attestations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    pda TEXT NOT NULL,
+    address TEXT NOT NULL,
+    age INTEGER NOT NULL,
+    country INTEGER NOT NULL,
+    -- Unix seconds.
+    expiry INTEGER NOT NULL,
+    signature TEXT NOT NULL UNIQUE,
+    slot INTEGER NOT NULL,
+    -- Who asked for it. `job_id` is set if it was issued by a verification job.
+    requester_ip TEXT,
+    user_agent TEXT,
+    job_id TEXT,
+    created_at INTEGER NOT NULL
+);
+
+CREATE INDEX attestations_pda ON attestations (pda);
+
+-- A PDA can be attested again once revoked, so revocations apply to earlier slots only.
+CREATE TABLE revocations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    pda TEXT NOT NULL,
+    address TEXT NOT NULL,
+    signature TEXT NOT NULL UNIQUE,
+    slot INTEGER NOT NULL,
+    reason TEXT,
+    created_at INTEGER NOT NULL
+);
+
+CREATE INDEX revocations_pda ON revocations (pda);
+
+ALTER TABLE verification_jobs ADD COLUMN requester_ip TEXT;
+ALTER TABLE verification_jobs ADD COLUMN user_agent TEXT;
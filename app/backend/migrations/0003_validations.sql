@@ -0,0 +1,14 @@
+-- `ValidationResult` events of the validate program, see `src/indexer.rs`.
+CREATE TABLE validations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    address TEXT NOT NULL,
+    valid INTEGER NOT NULL,
+    signature TEXT NOT NULL,
+    slot INTEGER NOT NULL,
+    -- Unix seconds the event was indexed at.
+    created_at INTEGER NOT NULL,
+    -- Notifications are delivered again after a resubscription.
+    UNIQUE (signature, address)
+);
+
+CREATE INDEX validations_address ON validations (address, slot);
@@ -0,0 +1,8 @@
+-- Attestations reminded about before expiring, see `src/expiry.rs`.
+-- Keyed by expiry as well, so that a renewed attestation is reminded about again.
+CREATE TABLE expiry_reminders (
+    pda TEXT NOT NULL,
+    expiry INTEGER NOT NULL,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (pda, expiry)
+);
@@ -0,0 +1,5 @@
+-- Lamports payer spent per UTC day (days since epoch), see `DailySpendCap` in `src/limits.rs`.
+CREATE TABLE payer_spend (
+    day INTEGER PRIMARY KEY,
+    lamports INTEGER NOT NULL
+);
@@ -0,0 +1,129 @@
+//! One-off commands run instead of the server, e.g. `backend reconcile`.
+
+use std::{str::FromStr, sync::Arc};
+
+use anyhow::{anyhow, Context, Result};
+use sas_client::{transaction, AttestationService};
+use solana_sdk::{pubkey::Pubkey, signer::Signer};
+use tracing::{info, warn};
+
+use crate::{
+    config::Config,
+    db,
+    ledger::{self, Ledger},
+    limits::DailySpendCap,
+    verification::Claims,
+    webhooks::{WebhookEvent, Webhooks},
+};
+
+pub(crate) const USAGE: &str = "usage: backend [reconcile | revoke <address> [reason] \
+    | dead-letters | redeliver <id> | build-attestation <address> [nonce] | submit <transaction>]";
+
+async fn setup() -> Result<(AttestationService, Ledger, Webhooks)> {
+    let config = Config::load().context("invalid configuration")?;
+    let mut sas = AttestationService::from_config(&config.solana)?;
+    let db = db::connect(&config.database.url).await?;
+    if let Some(cap) = config.solana.balance.daily_spend_cap {
+        sas = sas.with_spend_cap(Arc::new(DailySpendCap::new(db.clone(), cap)));
+    }
+    let webhooks = Webhooks::new(db.clone(), &config.webhooks)?;
+    Ok((sas, Ledger::new(db), webhooks))
+}
+
+/// Prints a JSON report of how the ledger differs from attestations on-chain.
+/// Fails if it does, so that it can run as a periodic check.
+pub(crate) async fn reconcile() -> Result<()> {
+    let (sas, ledger, _) = setup().await?;
+    let accounts = sas
+        .list_attestations()
+        .await
+        .context("couldn't list attestations on-chain")?;
+    let entries = ledger.active().await.context("couldn't read the ledger")?;
+
+    let report = ledger::reconcile(entries, &accounts);
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    if !report.is_consistent() {
+        return Err(anyhow!(
+            "ledger disagrees with the chain: {} missing on-chain, {} unrecorded, {} mismatched",
+            report.missing_on_chain.len(),
+            report.unrecorded.len(),
+            report.mismatched.len()
+        ));
+    }
+    info!(attestations = report.on_chain, "ledger matches the chain");
+    Ok(())
+}
+
+/// Closes the attestation of `address` and records the revocation.
+/// The webhook event is delivered by the server.
+pub(crate) async fn revoke(address: &str, reason: Option<&str>) -> Result<()> {
+    let user =
+        Pubkey::from_str(address).with_context(|| format!("{address:?} isn't a valid address"))?;
+    let (sas, ledger, webhooks) = setup().await?;
+    let revoked = sas.revoke_attestation(user).await?;
+    println!("{}", revoked.signature);
+    webhooks
+        .emit(WebhookEvent::revoked(&user, &revoked, reason))
+        .await;
+    if let Err(err) = ledger.record_revoked(&user, &revoked, reason).await {
+        warn!(%err, signature = %revoked.signature, "revoked, but couldn't record it in the ledger");
+        return Err(err.context("couldn't record the revocation"));
+    }
+    Ok(())
+}
+
+/// Prints webhook deliveries that ran out of attempts, as JSON.
+pub(crate) async fn dead_letters() -> Result<()> {
+    let (_, _, webhooks) = setup().await?;
+    let dead = webhooks
+        .dead_letters()
+        .await
+        .context("couldn't read dead letters")?;
+    println!("{}", serde_json::to_string_pretty(&dead)?);
+    Ok(())
+}
+
+/// Queues a dead webhook delivery again, for the server to deliver.
+pub(crate) async fn redeliver(id: &str) -> Result<()> {
+    let id = id
+        .parse()
+        .with_context(|| format!("{id:?} isn't a delivery id"))?;
+    let (_, _, webhooks) = setup().await?;
+    if !webhooks.redeliver(id).await? {
+        return Err(anyhow!("no dead webhook delivery {id}"));
+    }
+    info!(id, "webhook delivery queued again");
+    Ok(())
+}
+
+/// Prints a base64 attestation transaction signed by payer only, for an offline signer.
+/// Built against `nonce`, whose authority has to be payer, it stays valid until the nonce
+/// is advanced. Otherwise, it has to be submitted within a couple of minutes.
+pub(crate) async fn build_attestation(address: &str, nonce: Option<&str>) -> Result<()> {
+    let user =
+        Pubkey::from_str(address).with_context(|| format!("{address:?} isn't a valid address"))?;
+    let (sas, _, _) = setup().await?;
+    let payload = Claims::VERIFIED.into();
+    let tx = match nonce {
+        Some(nonce) => {
+            let nonce = Pubkey::from_str(nonce)
+                .with_context(|| format!("{nonce:?} isn't a valid nonce account"))?;
+            sas.build_durable_attestation(user, payload, nonce, sas.payer().pubkey())
+                .await?
+        }
+        None => sas.build_attestation(user, payload).await?,
+    };
+    info!(missing = ?transaction::missing_signers(&tx), "built attestation transaction");
+    println!("{}", transaction::encode_transaction(&tx)?);
+    Ok(())
+}
+
+/// Submits a base64 transaction signed elsewhere, e.g. one from `build-attestation`.
+/// Attestations submitted this way aren't recorded in the ledger, `reconcile` reports them.
+pub(crate) async fn submit(encoded: &str) -> Result<()> {
+    let tx = transaction::decode_transaction(encoded).context("invalid transaction")?;
+    let (sas, _, _) = setup().await?;
+    let signature = sas.submit_transaction(&tx).await?;
+    println!("{signature}");
+    Ok(())
+}
@@ -0,0 +1,376 @@
+use std::{net::SocketAddr, path::PathBuf, str::FromStr, time::Duration};
+
+use anyhow::{anyhow, Context, Result};
+use sas_client::ServiceConfig;
+use serde::{Deserialize, Deserializer};
+use solana_sdk::pubkey::Pubkey;
+
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+/// Backend configuration, read from a TOML file and overridden by environment variables.
+/// See `config.example.toml` for the layout.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Config {
+    pub server: ServerConfig,
+    pub health: HealthConfig,
+    pub limits: LimitsConfig,
+    pub database: DatabaseConfig,
+    pub jobs: JobsConfig,
+    pub webhooks: WebhooksConfig,
+    pub expiry: ExpiryConfig,
+    pub solana: ServiceConfig,
+    pub programs: ProgramsConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ServerConfig {
+    pub bind: SocketAddr,
+    /// How long in-flight requests get to finish after SIGTERM/SIGINT.
+    pub shutdown_timeout_secs: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: SocketAddr::from(([0, 0, 0, 0], 3000)),
+            shutdown_timeout_secs: 30,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout_secs)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct HealthConfig {
+    /// `/readyz` fails if the latest RPC slot is older than this.
+    pub max_slot_age_secs: u64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            max_slot_age_secs: 60,
+        }
+    }
+}
+
+impl HealthConfig {
+    pub fn max_slot_age(&self) -> Duration {
+        Duration::from_secs(self.max_slot_age_secs)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct LimitsConfig {
+    /// Requests per minute from a client IP, 0 disables the limit.
+    pub per_ip_per_minute: u32,
+    /// Requests per minute about a wallet, 0 disables the limit.
+    pub per_wallet_per_minute: u32,
+    /// Transactions paid by payer that may be in flight at once.
+    pub max_concurrent_transactions: usize,
+    /// `/attestations/events` streams open at once, 0 turns the endpoint off.
+    pub max_event_streams: usize,
+    /// Take client IP from `X-Forwarded-For`. Only safe behind a proxy that sets it.
+    pub trust_forwarded_for: bool,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            per_ip_per_minute: 60,
+            per_wallet_per_minute: 5,
+            max_concurrent_transactions: 16,
+            max_event_streams: 1000,
+            trust_forwarded_for: false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct DatabaseConfig {
+    /// SQLite database, created if missing.
+    pub url: String,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: "sqlite://backend.db".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct JobsConfig {
+    /// Verification jobs processed at once.
+    pub workers: usize,
+    /// Attempts before a job fails for good.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled on every next one.
+    pub retry_delay_secs: u64,
+}
+
+impl Default for JobsConfig {
+    fn default() -> Self {
+        Self {
+            workers: 4,
+            max_attempts: 5,
+            retry_delay_secs: 5,
+        }
+    }
+}
+
+impl JobsConfig {
+    pub fn retry_delay(&self) -> Duration {
+        Duration::from_secs(self.retry_delay_secs)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct WebhooksConfig {
+    /// Endpoints every event is POSTed to. None disables webhooks.
+    pub urls: Vec<String>,
+    /// HMAC key of the `X-Webhook-Signature` header. Required with `urls`.
+    pub secret: Option<String>,
+    /// Attempts before a delivery is moved to the dead-letter log.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled on every next one.
+    pub retry_delay_secs: u64,
+    pub timeout_secs: u64,
+    /// `validation.failed` is sent once per address within this, 0 sends every one.
+    pub validation_failed_window_secs: u64,
+    /// Delivered events are deleted after this.
+    pub delivered_retention_secs: u64,
+}
+
+impl Default for WebhooksConfig {
+    fn default() -> Self {
+        Self {
+            urls: Vec::new(),
+            secret: None,
+            max_attempts: 8,
+            retry_delay_secs: 10,
+            timeout_secs: 10,
+            validation_failed_window_secs: 3600,
+            delivered_retention_secs: 7 * 24 * 60 * 60,
+        }
+    }
+}
+
+impl WebhooksConfig {
+    pub fn retry_delay(&self) -> Duration {
+        Duration::from_secs(self.retry_delay_secs)
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+
+    pub fn validation_failed_window(&self) -> Duration {
+        Duration::from_secs(self.validation_failed_window_secs)
+    }
+
+    pub fn delivered_retention(&self) -> Duration {
+        Duration::from_secs(self.delivered_retention_secs)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ExpiryConfig {
+    /// Attestations expiring within this are renewed or reminded about.
+    pub window_secs: u64,
+    /// How often the ledger is scanned.
+    pub interval_secs: u64,
+    /// Renew instead of only reminding.
+    pub auto_renew: bool,
+}
+
+impl Default for ExpiryConfig {
+    fn default() -> Self {
+        Self {
+            window_secs: 3 * 24 * 60 * 60,
+            interval_secs: 60 * 60,
+            auto_renew: false,
+        }
+    }
+}
+
+impl ExpiryConfig {
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window_secs)
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ProgramsConfig {
+    #[serde(deserialize_with = "deserialize_pubkey")]
+    pub validate: Pubkey,
+}
+
+impl Default for ProgramsConfig {
+    fn default() -> Self {
+        Self {
+            validate: test_solana_program::ID,
+        }
+    }
+}
+
+fn deserialize_pubkey<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pubkey, D::Error> {
+    let pubkey = String::deserialize(deserializer)?;
+    Pubkey::from_str(&pubkey).map_err(serde::de::Error::custom)
+}
+
+impl Config {
+    /// Reads the file at `CONFIG_PATH`, or `config.toml` if it exists,
+    /// and overrides it with environment variables:
+    /// `BIND_ADDRESS`, `SHUTDOWN_TIMEOUT_SECS`, `MAX_SLOT_AGE_SECS`,
+    /// `RATE_LIMIT_PER_{IP,WALLET}`, `MAX_CONCURRENT_TRANSACTIONS`, `MAX_EVENT_STREAMS`,
+    /// `TRUST_FORWARDED_FOR`, `DATABASE_URL`, `JOB_WORKERS`, `JOB_MAX_ATTEMPTS`,
+    /// `WEBHOOK_URLS` (comma-separated), `WEBHOOK_SECRET`, `EXPIRY_WINDOW_SECS`,
+    /// `EXPIRY_AUTO_RENEW`, `VALIDATE_PROGRAM_ID`, and those of [`ServiceConfig::apply_env`].
+    pub fn load() -> Result<Self> {
+        let path = std::env::var("CONFIG_PATH").map(PathBuf::from);
+        let mut config = match path {
+            Ok(path) => Self::read(path)?,
+            Err(_) if std::fs::exists(DEFAULT_CONFIG_PATH)? => {
+                Self::read(DEFAULT_CONFIG_PATH.into())?
+            }
+            Err(_) => Self::default(),
+        };
+
+        if let Ok(bind) = std::env::var("BIND_ADDRESS") {
+            config.server.bind = bind
+                .parse()
+                .with_context(|| format!("BIND_ADDRESS {bind:?} isn't a socket address"))?;
+        }
+        env_number(
+            "SHUTDOWN_TIMEOUT_SECS",
+            &mut config.server.shutdown_timeout_secs,
+        )?;
+        env_number("MAX_SLOT_AGE_SECS", &mut config.health.max_slot_age_secs)?;
+        env_number("RATE_LIMIT_PER_IP", &mut config.limits.per_ip_per_minute)?;
+        env_number(
+            "RATE_LIMIT_PER_WALLET",
+            &mut config.limits.per_wallet_per_minute,
+        )?;
+        env_number(
+            "MAX_CONCURRENT_TRANSACTIONS",
+            &mut config.limits.max_concurrent_transactions,
+        )?;
+        env_number("MAX_EVENT_STREAMS", &mut config.limits.max_event_streams)?;
+        if let Ok(trust) = std::env::var("TRUST_FORWARDED_FOR") {
+            config.limits.trust_forwarded_for = trust
+                .parse()
+                .with_context(|| format!("TRUST_FORWARDED_FOR has to be a bool, got {trust:?}"))?;
+        }
+        if let Ok(url) = std::env::var("DATABASE_URL") {
+            config.database.url = url;
+        }
+        env_number("JOB_WORKERS", &mut config.jobs.workers)?;
+        env_number("JOB_MAX_ATTEMPTS", &mut config.jobs.max_attempts)?;
+        if let Ok(urls) = std::env::var("WEBHOOK_URLS") {
+            config.webhooks.urls = urls
+                .split(',')
+                .map(str::trim)
+                .filter(|url| !url.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        if let Ok(secret) = std::env::var("WEBHOOK_SECRET") {
+            config.webhooks.secret = Some(secret);
+        }
+        env_number("EXPIRY_WINDOW_SECS", &mut config.expiry.window_secs)?;
+        if let Ok(renew) = std::env::var("EXPIRY_AUTO_RENEW") {
+            config.expiry.auto_renew = renew
+                .parse()
+                .with_context(|| format!("EXPIRY_AUTO_RENEW has to be a bool, got {renew:?}"))?;
+        }
+        if let Ok(program) = std::env::var("VALIDATE_PROGRAM_ID") {
+            config.programs.validate = program
+                .parse()
+                .with_context(|| format!("VALIDATE_PROGRAM_ID {program:?} isn't a pubkey"))?;
+        }
+        config.solana.apply_env()?;
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn read(path: PathBuf) -> Result<Self> {
+        let contents = std::fs::read_to_string(&path)
+            .with_context(|| format!("couldn't read config {}", path.display()))?;
+        toml::from_str(&contents).with_context(|| format!("invalid config {}", path.display()))
+    }
+
+    /// Catches what deserialization can't, before anything is started.
+    /// Keypairs are checked when [`sas_client::AttestationService`] is built from the config.
+    fn validate(&self) -> Result<()> {
+        self.solana.cluster()?;
+        self.solana.attestation_expiry()?;
+        if self.solana.payer.is_none() {
+            return Err(anyhow!("solana.payer (or PAYER_CREDS) is required"));
+        }
+        if self.limits.max_concurrent_transactions == 0 {
+            return Err(anyhow!(
+                "limits.max_concurrent_transactions has to be positive"
+            ));
+        }
+        if self.jobs.workers == 0 || self.jobs.max_attempts == 0 {
+            return Err(anyhow!(
+                "jobs.workers and jobs.max_attempts have to be positive"
+            ));
+        }
+        if self.expiry.interval_secs == 0 {
+            return Err(anyhow!("expiry.interval_secs has to be positive"));
+        }
+        // Otherwise renewed attestations would be renewed again on every scan.
+        if self.expiry.auto_renew && self.expiry.window_secs >= self.solana.attestation_expiry_secs
+        {
+            return Err(anyhow!(
+                "expiry.window_secs has to be shorter than solana.attestation_expiry_secs"
+            ));
+        }
+        if !self.webhooks.urls.is_empty() {
+            if self.webhooks.secret.as_deref().is_none_or(str::is_empty) {
+                return Err(anyhow!(
+                    "webhooks.secret (or WEBHOOK_SECRET) is required with webhooks.urls"
+                ));
+            }
+            if self.webhooks.max_attempts == 0 {
+                return Err(anyhow!("webhooks.max_attempts has to be positive"));
+            }
+            for url in &self.webhooks.urls {
+                reqwest::Url::parse(url)
+                    .with_context(|| format!("webhook URL {url:?} is invalid"))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Overrides `target` with environment variable `name`, if it's set.
+fn env_number<T: FromStr>(name: &str, target: &mut T) -> Result<()> {
+    if let Ok(value) = std::env::var(name) {
+        *target = value
+            .parse()
+            .map_err(|_| anyhow!("{name} has to be a number, got {value:?}"))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,189 @@
+use std::time::Duration;
+
+use anchor_client::solana_client::client_error::{ClientError, ClientErrorKind};
+use axum::{
+    extract::rejection::{JsonRejection, PathRejection, QueryRejection},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use metrics::counter;
+use sas_client::{InsufficientFunds, SpendCapReached};
+use serde::Serialize;
+use tracing::{error, warn};
+use utoipa::ToSchema;
+
+use crate::telemetry::RATE_LIMITED;
+
+/// Error of any handler, responded to with a matching status and
+/// `{"error": {"code": ..., "message": ...}}`.
+#[derive(Debug, Clone)]
+pub(crate) enum ApiError {
+    /// 400, the request is malformed, e.g. an address isn't a valid pubkey.
+    BadRequest { code: &'static str, message: String },
+    /// 404, e.g. there's no attestation for the address.
+    NotFound(String),
+    /// 429, with a `Retry-After` header. `limit` is the one that was hit.
+    RateLimited {
+        limit: &'static str,
+        message: String,
+        retry_after: Duration,
+    },
+    /// 502, the RPC node or the chain rejected a request.
+    Upstream(String),
+    /// 503, the RPC node or the database is unreachable, or payer can't afford transactions.
+    Unavailable { code: &'static str, message: String },
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct ErrorBody {
+    error: ErrorDetails,
+}
+
+/// The `error` object of error responses.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub(crate) struct ErrorDetails {
+    pub code: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after_secs: Option<u64>,
+}
+
+impl ApiError {
+    pub fn invalid_address(address: &str) -> Self {
+        Self::BadRequest {
+            code: "invalid_address",
+            message: format!("{address:?} isn't a valid address"),
+        }
+    }
+
+    /// 503 for a failed database query. The cause is logged, not exposed.
+    pub fn database(err: &anyhow::Error, message: &str) -> Self {
+        error!("{message}: {err:#}");
+        Self::Unavailable {
+            code: "database_error",
+            message: message.to_string(),
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self {
+            Self::BadRequest { .. } => StatusCode::BAD_REQUEST,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::Upstream(_) => StatusCode::BAD_GATEWAY,
+            Self::Unavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let (code, message, retry_after) = match self {
+            Self::BadRequest { code, message } | Self::Unavailable { code, message } => {
+                (code, message, None)
+            }
+            Self::NotFound(message) => ("not_found", message, None),
+            Self::RateLimited {
+                limit,
+                message,
+                retry_after,
+            } => {
+                counter!(RATE_LIMITED, "limit" => limit).increment(1);
+                let secs = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+                ("rate_limited", message, Some(secs))
+            }
+            Self::Upstream(message) => ("upstream_error", message, None),
+        };
+        if status.is_server_error() {
+            warn!(%status, code, %message, "request failed");
+        }
+
+        let body = Json(ErrorBody {
+            error: ErrorDetails {
+                code,
+                message,
+                retry_after_secs: retry_after,
+            },
+        });
+        match retry_after {
+            Some(secs) => (status, [(header::RETRY_AFTER, secs.to_string())], body).into_response(),
+            None => (status, body).into_response(),
+        }
+    }
+}
+
+impl From<&SpendCapReached> for ApiError {
+    fn from(err: &SpendCapReached) -> Self {
+        Self::RateLimited {
+            limit: "daily_spend",
+            message: "daily spending limit reached".to_string(),
+            retry_after: err.resets_in,
+        }
+    }
+}
+
+impl From<&ClientError> for ApiError {
+    fn from(err: &ClientError) -> Self {
+        match &err.kind {
+            ClientErrorKind::Reqwest(reqwest) if reqwest.is_connect() || reqwest.is_timeout() => {
+                Self::Unavailable {
+                    code: "rpc_unavailable",
+                    message: "RPC node is unreachable".to_string(),
+                }
+            }
+            _ => Self::Upstream(err.to_string()),
+        }
+    }
+}
+
+/// Classifies errors of [`sas_client::AttestationService`] by their cause.
+/// Unclassified ones are logged and responded to with a fixed message.
+impl From<anyhow::Error> for ApiError {
+    fn from(err: anyhow::Error) -> Self {
+        if let Some(cap) = err.downcast_ref::<SpendCapReached>() {
+            return cap.into();
+        }
+        if err.is::<InsufficientFunds>() {
+            return Self::Unavailable {
+                code: "insufficient_funds",
+                message: "payer can't afford transactions right now".to_string(),
+            };
+        }
+        if let Some(client_err) = err.downcast_ref::<ClientError>() {
+            return client_err.into();
+        }
+        // The chain may reveal internals, e.g. addresses and RPC responses, so it's only logged.
+        let message = "upstream request failed";
+        error!("{message}: {err:#}");
+        Self::Upstream(message.to_string())
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self::BadRequest {
+            code: "invalid_body",
+            message: rejection.body_text(),
+        }
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::BadRequest {
+            code: "invalid_query",
+            message: rejection.body_text(),
+        }
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        Self::BadRequest {
+            code: "invalid_path",
+            message: rejection.body_text(),
+        }
+    }
+}
@@ -0,0 +1,317 @@
+//! Live attestation updates over Server-Sent Events, so clients don't have to poll.
+//! Every stream is fed by one subscription of the process, see [`AttestationEvents`].
+
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    pin::pin,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use axum::{
+    extract::{rejection::QueryRejection, Query},
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures_util::{stream, Stream, StreamExt};
+use sas_client::{AttestationService, AttestationUpdate};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use tokio::{
+    sync::{
+        broadcast::{self, error::RecvError},
+        OwnedSemaphorePermit,
+    },
+    time::MissedTickBehavior,
+};
+use tracing::{field, info, instrument, warn, Span};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    error::{ApiError, ErrorBody},
+    verification::Claims,
+    AppState,
+};
+
+/// Updates buffered for streams that fall behind, before they miss some.
+const UPDATES_CAPACITY: usize = 1024;
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(5);
+/// How often attestations streamed by address are checked for revocations.
+const REVOCATION_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Deserialize, Clone, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct EventsQuery {
+    /// Only this wallet's attestation. Every attestation if missing, without revocations.
+    address: Option<String>,
+}
+
+/// Data of an `attested` or `revoked` event.
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct AttestationEvent {
+    /// Wallet the attestation is for.
+    address: Option<String>,
+    attestation: String,
+    /// `null` once revoked.
+    payload: Option<Claims>,
+    /// Unix timestamp the attestation is valid until, `null` once revoked.
+    expiry: Option<i64>,
+    slot: u64,
+}
+
+/// `address` is the subscribed wallet, which revocations don't carry.
+fn to_event(update: AttestationUpdate, address: Option<&Pubkey>) -> Event {
+    let (name, data) = match update {
+        AttestationUpdate::Attested { slot, attestation } => (
+            "attested",
+            AttestationEvent {
+                address: Some(attestation.user.to_string()),
+                attestation: attestation.pda.to_string(),
+                payload: Some(attestation.payload.into()),
+                expiry: Some(attestation.expiry),
+                slot,
+            },
+        ),
+        AttestationUpdate::Closed { slot, pda } => (
+            "revoked",
+            AttestationEvent {
+                address: address.map(Pubkey::to_string),
+                attestation: pda.to_string(),
+                payload: None,
+                expiry: None,
+                slot,
+            },
+        ),
+    };
+    Event::default()
+        .event(name)
+        .json_data(data)
+        .expect("attestation events serialize")
+}
+
+/// Fans out one subscription to every attestation to all `/attestations/events` streams.
+/// It doesn't include revocations, so attestations streamed by address are polled for them.
+pub(crate) struct AttestationEvents {
+    updates: broadcast::Sender<AttestationUpdate>,
+    /// PDAs of attestations streamed by address.
+    watched: Mutex<HashMap<Pubkey, Watched>>,
+}
+
+#[derive(Debug, Default)]
+struct Watched {
+    streams: usize,
+    /// Whether the account was open when last polled, `None` until then.
+    open: Option<bool>,
+}
+
+impl AttestationEvents {
+    pub fn new() -> Self {
+        Self {
+            updates: broadcast::channel(UPDATES_CAPACITY).0,
+            watched: Mutex::default(),
+        }
+    }
+
+    fn watch(&self, pda: Pubkey) {
+        self.watched.lock().unwrap().entry(pda).or_default().streams += 1;
+    }
+
+    fn unwatch(&self, pda: &Pubkey) {
+        let mut watched = self.watched.lock().unwrap();
+        if let Some(entry) = watched.get_mut(pda) {
+            entry.streams -= 1;
+            if entry.streams == 0 {
+                watched.remove(pda);
+            }
+        }
+    }
+
+    fn watched(&self) -> Vec<Pubkey> {
+        self.watched.lock().unwrap().keys().copied().collect()
+    }
+
+    /// Publishes revocations of attestations that were open at the last poll.
+    fn record_poll(&self, slot: u64, pdas: &[Pubkey], open: &[bool]) {
+        let mut watched = self.watched.lock().unwrap();
+        for (pda, &open) in pdas.iter().zip(open) {
+            let Some(entry) = watched.get_mut(pda) else {
+                continue;
+            };
+            if entry.open.replace(open) == Some(true) && !open {
+                _ = self
+                    .updates
+                    .send(AttestationUpdate::Closed { slot, pda: *pda });
+            }
+        }
+    }
+}
+
+/// Feeds [`AttestationEvents`] as long as the process runs, resubscribing whenever
+/// the websocket drops. Updates landing while disconnected are missed.
+pub(crate) async fn run(state: &AppState) {
+    tokio::join!(forward_updates(state), poll_revocations(state));
+}
+
+async fn forward_updates(state: &AppState) {
+    loop {
+        if let Err(err) = forward(state).await {
+            warn!(%err, "attestation update subscription failed");
+        }
+        tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+    }
+}
+
+async fn forward(state: &AppState) -> Result<()> {
+    let mut updates = pin!(state.sas.subscribe_all().await?);
+    info!("streaming attestation updates");
+    while let Some(update) = updates.next().await {
+        // Fails only while no stream is open.
+        _ = state.events.updates.send(update);
+    }
+    Err(anyhow!("attestation update subscription closed"))
+}
+
+async fn poll_revocations(state: &AppState) {
+    let mut interval = tokio::time::interval(REVOCATION_POLL_INTERVAL);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        interval.tick().await;
+        let pdas = state.events.watched();
+        if pdas.is_empty() {
+            continue;
+        }
+        match state.sas.attestations_exist(&pdas).await {
+            Ok((slot, open)) => state.events.record_poll(slot, &pdas, &open),
+            Err(err) => warn!(%err, "couldn't poll streamed attestations"),
+        }
+    }
+}
+
+/// Whether a stream of the attestation at `pda`, or of every one if `None`, carries `update`.
+fn streams(update: &AttestationUpdate, pda: Option<&Pubkey>) -> bool {
+    match (update, pda) {
+        (_, Some(pda)) => update.pda() == *pda,
+        (AttestationUpdate::Attested { .. }, None) => true,
+        (AttestationUpdate::Closed { .. }, None) => false,
+    }
+}
+
+/// Held by an open stream, releasing its place in [`AttestationEvents`] once dropped.
+struct StreamGuard {
+    state: Arc<AppState>,
+    pda: Option<Pubkey>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        if let Some(pda) = &self.pda {
+            self.state.events.unwatch(pda);
+        }
+    }
+}
+
+/// Streams `attested` and `revoked` events of the attestation of `address`, or of every one.
+/// Updates landing while the backend resubscribes to the RPC websocket are missed.
+/// Streams end on shutdown, so they don't hold it up.
+#[utoipa::path(
+    get,
+    path = "/attestations/events",
+    tag = "attestations",
+    params(EventsQuery),
+    responses(
+        (status = 200, description = "Server-Sent Events named `attested` or `revoked`",
+            content_type = "text/event-stream", body = AttestationEvent),
+        (status = 400, description = "Malformed request", body = ErrorBody),
+        (status = 429, description = "Rate limited, or too many streams open", body = ErrorBody),
+    )
+)]
+#[instrument(skip_all, fields(pubkey = field::Empty))]
+pub(crate) async fn events_handler(
+    query: Result<Query<EventsQuery>, QueryRejection>,
+    state: Arc<AppState>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let Query(query) = query?;
+    let user = match &query.address {
+        Some(address) => {
+            Span::current().record("pubkey", address);
+            Some(Pubkey::from_str(address).map_err(|_| ApiError::invalid_address(address))?)
+        }
+        None => None,
+    };
+    let permit = state.limits.event_stream_permit()?;
+
+    let pda = user.map(|user| {
+        AttestationService::attestation_pda(state.sas.cred_pda, state.sas.schema_pda, user)
+    });
+    if let Some(pda) = pda {
+        state.events.watch(pda);
+    }
+    let updates = state.events.updates.subscribe();
+    let shutdown = state.shutting_down();
+    let guard = StreamGuard {
+        state,
+        pda,
+        _permit: permit,
+    };
+    info!("streaming attestation updates");
+
+    let events = stream::unfold((updates, guard), move |(mut updates, guard)| async move {
+        loop {
+            match updates.recv().await {
+                Ok(update) if streams(&update, guard.pda.as_ref()) => {
+                    let event = to_event(update, user.as_ref());
+                    return Some((Ok(event), (updates, guard)));
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(missed)) => warn!(missed, "event stream fell behind"),
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+    .take_until(shutdown);
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_streams_filter() {
+        let (pda, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let closed = AttestationUpdate::Closed { slot: 1, pda };
+        assert!(streams(&closed, Some(&pda)));
+        assert!(!streams(&closed, Some(&other)));
+        assert!(!streams(&closed, None));
+    }
+
+    #[test]
+    fn test_revocations_of_watched_attestations() {
+        let events = AttestationEvents::new();
+        let mut updates = events.updates.subscribe();
+        let (pda, unwatched) = (Pubkey::new_unique(), Pubkey::new_unique());
+        events.watch(pda);
+        events.watch(pda);
+
+        // Closed since it was first polled, or never open: nothing to revoke.
+        events.record_poll(1, &[pda, unwatched], &[false, true]);
+        events.record_poll(2, &[pda, unwatched], &[true, false]);
+        assert!(updates.try_recv().is_err());
+
+        events.record_poll(3, &[pda], &[false]);
+        let update = updates.try_recv().unwrap();
+        assert!(
+            matches!(update, AttestationUpdate::Closed { slot: 3, pda: closed } if closed == pda)
+        );
+        events.record_poll(4, &[pda], &[false]);
+        assert!(updates.try_recv().is_err());
+
+        events.unwatch(&pda);
+        assert_eq!(events.watched(), vec![pda]);
+        events.unwatch(&pda);
+        assert!(events.watched().is_empty());
+    }
+}
@@ -0,0 +1,128 @@
+//! Expiry scheduler: periodically finds attestations in the ledger that expire soon,
+//! and renews them or emits `attestation.expiring` webhooks.
+
+use std::{str::FromStr, sync::Arc};
+
+use anyhow::Result;
+use metrics::counter;
+use sas_client::AttestationPayload;
+use solana_sdk::pubkey::Pubkey;
+use tokio::task::JoinHandle;
+use tracing::{error, info, instrument, warn};
+
+use crate::{
+    config::ExpiryConfig,
+    db::unix_now,
+    ledger::{LedgerEntry, Requester},
+    telemetry::EXPIRING_ATTESTATIONS,
+    webhooks::WebhookEvent,
+    AppState,
+};
+
+pub(crate) fn spawn_scheduler(state: &Arc<AppState>, config: ExpiryConfig) -> JoinHandle<()> {
+    let state = Arc::clone(state);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(config.interval());
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = state.shutting_down() => return,
+            }
+            if let Err(err) = scan(&state, &config).await {
+                error!(%err, "couldn't scan for expiring attestations");
+            }
+        }
+    })
+}
+
+/// Handles active attestations expiring within the window. Already expired ones are left alone.
+async fn scan(state: &AppState, config: &ExpiryConfig) -> Result<()> {
+    let now = unix_now();
+    let until = now + config.window().as_secs() as i64;
+    let expiring = state.ledger.active_expiring(now, until).await?;
+    if !expiring.is_empty() {
+        info!(count = expiring.len(), "found expiring attestations");
+    }
+
+    for entry in expiring {
+        // The rest is handled by the first scan after a restart.
+        if state.is_shutting_down() {
+            break;
+        }
+        if config.auto_renew {
+            match renew(state, &entry).await {
+                Ok(()) => {
+                    counter!(EXPIRING_ATTESTATIONS, "action" => "renewed").increment(1);
+                    continue;
+                }
+                Err(err) => {
+                    counter!(EXPIRING_ATTESTATIONS, "action" => "failed").increment(1);
+                    warn!(%err, pda = entry.pda, "couldn't renew attestation, reminding instead");
+                }
+            }
+        }
+        // One failed reminder shouldn't hold up the rest, it's retried on the next scan.
+        if let Err(err) = remind(state, &entry).await {
+            error!(%err, pda = entry.pda, "couldn't remind about expiring attestation");
+        }
+    }
+    Ok(())
+}
+
+/// Emits `attestation.expiring`, once per attestation and expiry.
+async fn remind(state: &AppState, entry: &LedgerEntry) -> Result<()> {
+    if !state
+        .ledger
+        .record_reminder(&entry.pda, entry.expiry)
+        .await?
+    {
+        return Ok(());
+    }
+    counter!(EXPIRING_ATTESTATIONS, "action" => "reminded").increment(1);
+    let event = WebhookEvent::AttestationExpiring {
+        address: entry.address.clone(),
+        attestation: entry.pda.clone(),
+        expiry: entry.expiry,
+    };
+    state.webhooks.emit(event).await;
+    Ok(())
+}
+
+/// Re-creates the attestation with the same claims and a fresh expiry, see
+/// [`sas_client::AttestationService::renew_attestation`]. If that fails, the attestation
+/// is left as it was.
+#[instrument(skip_all, fields(pda = entry.pda, pubkey = entry.address))]
+async fn renew(state: &AppState, entry: &LedgerEntry) -> Result<()> {
+    let user = Pubkey::from_str(&entry.address)?;
+    let payload = AttestationPayload {
+        age: entry.age,
+        country: entry.country,
+    };
+    let _permit = state.limits.wait_for_transaction_permit().await;
+    let issued = state.sas.renew_attestation(user, payload.clone()).await?;
+    info!(expiry = issued.expiry, signature = %issued.signature, "renewed attestation");
+
+    // The new issuance supersedes the old one, the ledger needs no revocation in between.
+    let requester = Requester {
+        ip: None,
+        user_agent: None,
+    };
+    if let Err(err) = state
+        .ledger
+        .record_issued(&user, &payload, &issued, &requester, None)
+        .await
+    {
+        error!(%err, "couldn't record renewed attestation in the ledger");
+    }
+
+    let event = WebhookEvent::AttestationRenewed {
+        address: entry.address.clone(),
+        attestation: entry.pda.clone(),
+        signature: issued.signature.to_string(),
+        slot: issued.slot,
+        expiry: issued.expiry,
+        previous_expiry: entry.expiry,
+    };
+    state.webhooks.emit(event).await;
+    Ok(())
+}
@@ -0,0 +1,31 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{http::StatusCode, Json};
+use sas_client::Readiness;
+use serde::Serialize;
+use tracing::warn;
+
+use crate::AppState;
+
+#[derive(Debug, Serialize, Clone, Copy)]
+pub(crate) struct Liveness {
+    alive: bool,
+}
+
+/// Succeeds as long as the process serves requests.
+pub(crate) async fn healthz_handler() -> Json<Liveness> {
+    Json(Liveness { alive: true })
+}
+
+/// Responds with 503 unless every check of [`sas_client::AttestationService::readiness`] passes.
+pub(crate) async fn readyz_handler(
+    state: Arc<AppState>,
+    max_slot_age: Duration,
+) -> (StatusCode, Json<Readiness>) {
+    let readiness = state.sas.readiness(max_slot_age).await;
+    if !readiness.ready {
+        warn!(?readiness, "not ready");
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(readiness));
+    }
+    (StatusCode::OK, Json(readiness))
+}
@@ -0,0 +1,399 @@
+//! Indexes `ValidationResult` events of the validate program, from its transaction logs,
+//! into a history of who was validated and when.
+
+use std::{str::FromStr, sync::Arc, time::Duration};
+
+use anchor_client::solana_client::{
+    nonblocking::pubsub_client::PubsubClient,
+    rpc_client::GetConfirmedSignaturesForAddress2Config,
+    rpc_config::{RpcTransactionConfig, RpcTransactionLogsConfig, RpcTransactionLogsFilter},
+};
+use anchor_lang::{AnchorDeserialize, Discriminator};
+use anyhow::{anyhow, Result};
+use axum::{
+    extract::{rejection::QueryRejection, Query},
+    Json,
+};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use futures_util::StreamExt;
+use metrics::counter;
+use serde::{Deserialize, Serialize};
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature};
+use sqlx::SqlitePool;
+use test_solana_program::ValidationResult;
+use tracing::{debug, error, info, warn};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    db::unix_now,
+    error::{ApiError, ErrorBody},
+    telemetry::VALIDATION_EVENTS,
+    AppState,
+};
+
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(5);
+const DEFAULT_HISTORY_LIMIT: u32 = 100;
+const MAX_HISTORY_LIMIT: u32 = 1000;
+/// Most transactions read when backfilling after a resubscription.
+const MAX_BACKFILL: usize = 10_000;
+
+/// A `ValidationResult` event, and the transaction that emitted it.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow, ToSchema)]
+pub(crate) struct ValidationRecord {
+    pub valid: bool,
+    pub signature: String,
+    pub slot: i64,
+    /// Unix seconds the event was indexed at.
+    pub indexed_at: i64,
+}
+
+pub(crate) struct Validations {
+    db: SqlitePool,
+}
+
+impl Validations {
+    pub fn new(db: SqlitePool) -> Self {
+        Self { db }
+    }
+
+    /// Returns whether the event is new, as notifications repeat after a resubscription.
+    pub async fn record(
+        &self,
+        event: &ValidationResult,
+        signature: &str,
+        slot: u64,
+    ) -> Result<bool> {
+        let inserted = sqlx::query(
+            "INSERT INTO validations (address, valid, signature, slot, created_at)
+            VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT (signature, address) DO NOTHING",
+        )
+        .bind(event.user.to_string())
+        .bind(event.valid)
+        .bind(signature)
+        .bind(slot as i64)
+        .bind(unix_now())
+        .execute(&self.db)
+        .await?
+        .rows_affected();
+        Ok(inserted > 0)
+    }
+
+    /// Slot of the latest indexed event, `None` if none was.
+    pub async fn last_slot(&self) -> Result<Option<u64>> {
+        let slot: Option<i64> = sqlx::query_scalar("SELECT max(slot) FROM validations")
+            .fetch_one(&self.db)
+            .await?;
+        Ok(slot.map(|slot| slot as u64))
+    }
+
+    /// Latest validations of `user` first.
+    pub async fn history(&self, user: &Pubkey, limit: u32) -> Result<Vec<ValidationRecord>> {
+        let records = sqlx::query_as(
+            "SELECT valid, signature, slot, created_at AS indexed_at
+            FROM validations
+            WHERE address = ?
+            ORDER BY slot DESC, id DESC
+            LIMIT ?",
+        )
+        .bind(user.to_string())
+        .bind(limit)
+        .fetch_all(&self.db)
+        .await?;
+        Ok(records)
+    }
+}
+
+/// Indexes events as long as the process runs, resubscribing whenever the websocket drops.
+/// Events emitted while disconnected are backfilled once resubscribed.
+pub(crate) async fn run(state: &AppState, commitment: CommitmentConfig) {
+    loop {
+        if let Err(err) = index(state, commitment).await {
+            warn!(%err, "validation event subscription failed");
+        }
+        tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+    }
+}
+
+async fn index(state: &AppState, commitment: CommitmentConfig) -> Result<()> {
+    let program_id = state.validate_program.id();
+    let pubsub = PubsubClient::new(state.sas.cluster().ws_url()).await?;
+    let (mut notifications, unsubscribe) = pubsub
+        .logs_subscribe(
+            RpcTransactionLogsFilter::Mentions(vec![program_id.to_string()]),
+            RpcTransactionLogsConfig {
+                commitment: Some(commitment),
+            },
+        )
+        .await?;
+    info!(%program_id, "indexing validation events");
+
+    // Subscribed first, so that nothing lands between the backfill and the subscription.
+    match backfill(state, commitment).await {
+        Ok(0) => {}
+        Ok(transactions) => info!(transactions, "backfilled validation events"),
+        Err(err) => warn!(%err, "couldn't backfill validation events"),
+    }
+
+    while let Some(notification) = notifications.next().await {
+        let logs = notification.value;
+        // Events of failed transactions were rolled back with them.
+        if logs.err.is_some() {
+            continue;
+        }
+        for event in validation_events(&program_id, &logs.logs) {
+            record(state, &event, &logs.signature, notification.context.slot).await;
+        }
+    }
+    unsubscribe().await;
+    Err(anyhow!("validation event subscription closed"))
+}
+
+/// Indexes transactions of the validate program since the last indexed slot, e.g. ones that
+/// landed while the websocket was down, going back at most [`MAX_BACKFILL`] transactions.
+/// Returns how many transactions were read.
+async fn backfill(state: &AppState, commitment: CommitmentConfig) -> Result<usize> {
+    let Some(since) = state.validations.last_slot().await? else {
+        return Ok(0);
+    };
+    let program_id = state.validate_program.id();
+    let rpc = state.validate_program.rpc();
+    // Signatures can't be listed at `processed`.
+    let commitment = if commitment.is_at_least_confirmed() {
+        commitment
+    } else {
+        CommitmentConfig::confirmed()
+    };
+
+    // Pages are latest first, so collect them before indexing in the order they landed.
+    let mut missed = Vec::new();
+    let mut before = None;
+    'pages: while missed.len() < MAX_BACKFILL {
+        let page = rpc
+            .get_signatures_for_address_with_config(
+                &program_id,
+                GetConfirmedSignaturesForAddress2Config {
+                    before,
+                    commitment: Some(commitment),
+                    ..GetConfirmedSignaturesForAddress2Config::default()
+                },
+            )
+            .await?;
+        let Some(last) = page.last() else {
+            break;
+        };
+        before = Some(Signature::from_str(&last.signature)?);
+        for status in page {
+            if status.slot < since {
+                break 'pages;
+            }
+            if status.err.is_none() {
+                missed.push((status.signature, status.slot));
+            }
+        }
+    }
+    missed.truncate(MAX_BACKFILL);
+
+    let config = RpcTransactionConfig {
+        commitment: Some(commitment),
+        max_supported_transaction_version: Some(0),
+        ..RpcTransactionConfig::default()
+    };
+    for (signature, slot) in missed.iter().rev() {
+        let transaction = rpc
+            .get_transaction_with_config(&Signature::from_str(signature)?, config)
+            .await?;
+        let logs: Option<Vec<String>> = transaction
+            .transaction
+            .meta
+            .and_then(|meta| meta.log_messages.into());
+        for event in validation_events(&program_id, &logs.unwrap_or_default()) {
+            record(state, &event, signature, *slot).await;
+        }
+    }
+    Ok(missed.len())
+}
+
+async fn record(state: &AppState, event: &ValidationResult, signature: &str, slot: u64) {
+    match state.validations.record(event, signature, slot).await {
+        Ok(true) => {
+            counter!(VALIDATION_EVENTS, "valid" => event.valid.to_string()).increment(1);
+            debug!(user = %event.user, event.valid, %signature, "indexed validation");
+        }
+        Ok(false) => {}
+        Err(err) => error!(%err, %signature, "couldn't record validation event"),
+    }
+}
+
+/// Decodes `ValidationResult` events emitted by `program_id` itself, not by programs it invokes
+/// or that invoke it, by following the invocation stack in `logs`.
+fn validation_events(program_id: &Pubkey, logs: &[String]) -> Vec<ValidationResult> {
+    let program_id = program_id.to_string();
+    let mut invoked = Vec::new();
+    let mut events = Vec::new();
+    for log in logs {
+        let Some(log) = log.strip_prefix("Program ") else {
+            continue;
+        };
+        if let Some(data) = log.strip_prefix("data: ") {
+            if invoked.last() != Some(&program_id.as_str()) {
+                continue;
+            }
+            match decode_event(data) {
+                Some(Ok(event)) => events.push(event),
+                Some(Err(err)) => warn!(%err, "couldn't decode validation event"),
+                None => {}
+            }
+        } else if let Some((program, rest)) = log.split_once(' ') {
+            if rest.starts_with("invoke [") {
+                invoked.push(program);
+            } else if rest == "success" || rest.starts_with("failed") {
+                invoked.pop();
+            }
+        }
+    }
+    events
+}
+
+/// `None` if `data` is another event.
+fn decode_event(data: &str) -> Option<Result<ValidationResult>> {
+    let data = BASE64_STANDARD.decode(data).ok()?;
+    let payload = data.strip_prefix(ValidationResult::DISCRIMINATOR)?;
+    Some(ValidationResult::try_from_slice(payload).map_err(Into::into))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct HistoryQuery {
+    /// Wallet whose validations are listed.
+    user: String,
+    /// Defaults to 100, at most 1000.
+    limit: Option<u32>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct HistoryResponse {
+    address: String,
+    /// Latest first.
+    validations: Vec<ValidationRecord>,
+}
+
+/// Validations of `user` indexed from `ValidationResult` events, latest first.
+#[utoipa::path(
+    get,
+    path = "/validations",
+    tag = "validation",
+    params(HistoryQuery),
+    responses(
+        (status = 200, body = HistoryResponse),
+        (status = 400, description = "Malformed request", body = ErrorBody),
+        (status = 429, description = "Rate limited", body = ErrorBody),
+        (status = 503, description = "Database unreachable", body = ErrorBody),
+    )
+)]
+pub(crate) async fn history_handler(
+    query: Result<Query<HistoryQuery>, QueryRejection>,
+    state: Arc<AppState>,
+) -> Result<Json<HistoryResponse>, ApiError> {
+    let Query(query) = query?;
+    let user = Pubkey::from_str(&query.user).map_err(|_| ApiError::invalid_address(&query.user))?;
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_HISTORY_LIMIT)
+        .min(MAX_HISTORY_LIMIT);
+    let validations = state
+        .validations
+        .history(&user, limit)
+        .await
+        .map_err(|err| ApiError::database(&err, "couldn't read validation history"))?;
+    Ok(Json(HistoryResponse {
+        address: query.user,
+        validations,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use anchor_lang::Event;
+
+    use super::*;
+
+    const PROGRAM: &str = "FSzAQ5gnGcpGTc6HoPb28JMBnVWyZ7Uj1NXZ2zrwYLyh";
+    const CALLER: &str = "Ca11er1111111111111111111111111111111111111";
+    /// `ValidationResult { user: [1..=32], valid: true }`, as `emit!` logs it.
+    const EVENT: &str = "Program data: PLQsLpNE9AMBAgMEBQYHCAkKCwwNDg8QERITFBUWFxgZGhscHR4fIAE=";
+    const USER: &str = "4wBqpZM9xaSheZzJSMawUKKwhdpChKbZ5eu5ky4Vigw";
+
+    fn event_log(user: Pubkey, valid: bool) -> String {
+        let data = ValidationResult { user, valid }.data();
+        format!("Program data: {}", BASE64_STANDARD.encode(data))
+    }
+
+    fn logs(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn test_validation_event() {
+        let logs = logs(&[
+            &format!("Program {PROGRAM} invoke [1]"),
+            "Program log: Instruction: Validate",
+            EVENT,
+            &format!("Program {PROGRAM} consumed 5127 of 200000 compute units"),
+            &format!("Program return: {PROGRAM} AQ=="),
+            &format!("Program {PROGRAM} success"),
+        ]);
+        let events = validation_events(&PROGRAM.parse().unwrap(), &logs);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].user.to_string(), USER);
+        assert!(events[0].valid);
+    }
+
+    #[test]
+    fn test_validation_events_of_invoked_program_only() {
+        let user = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        let logs = logs(&[
+            &format!("Program {CALLER} invoke [1]"),
+            "Program log: Instruction: Check",
+            // Emitted by the caller, not by the validate program.
+            &event_log(other, true),
+            &format!("Program {PROGRAM} invoke [2]"),
+            "Program log: Instruction: Validate",
+            // Another event of the validate program, and data that isn't an event at all.
+            "Program data: oOKFJfo3600AAAAA",
+            "Program data: AQID",
+            &event_log(user, false),
+            &format!("Program {PROGRAM} consumed 5127 of 194000 compute units"),
+            &format!("Program {PROGRAM} success"),
+            &event_log(other, true),
+            &format!("Program {CALLER} consumed 12000 of 200000 compute units"),
+            &format!("Program {CALLER} success"),
+            // A failed invocation pops the stack as well.
+            &format!("Program {PROGRAM} invoke [1]"),
+            &format!("Program {PROGRAM} failed: custom program error: 0x1770"),
+            &event_log(other, true),
+        ]);
+        let events = validation_events(&PROGRAM.parse().unwrap(), &logs);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].user, user);
+        assert!(!events[0].valid);
+    }
+
+    #[tokio::test]
+    async fn test_record_deduplicates_and_tracks_last_slot() {
+        let validations = Validations::new(crate::db::connect("sqlite::memory:").await.unwrap());
+        assert_eq!(validations.last_slot().await.unwrap(), None);
+
+        let user = Pubkey::new_unique();
+        let event = ValidationResult { user, valid: true };
+        assert!(validations.record(&event, "sig1", 7).await.unwrap());
+        assert!(!validations.record(&event, "sig1", 7).await.unwrap());
+        assert!(validations.record(&event, "sig2", 9).await.unwrap());
+        assert_eq!(validations.last_slot().await.unwrap(), Some(9));
+
+        let history = validations.history(&user, 10).await.unwrap();
+        let slots: Vec<_> = history.iter().map(|record| record.slot).collect();
+        assert_eq!(slots, vec![9, 7]);
+    }
+}
@@ -0,0 +1,416 @@
+//! Verification jobs: attestations issued in the background by a pool of workers.
+//! Jobs are stored in SQLite, so that pending ones are picked up again after a restart.
+
+use std::{str::FromStr, sync::Arc, time::Duration};
+
+use anyhow::{Context, Result};
+use axum::{
+    extract::{
+        rejection::{JsonRejection, PathRejection},
+        Path,
+    },
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Extension, Json,
+};
+use metrics::counter;
+use sas_client::{AttestationExists, IssuedAttestation, TransactionFailed};
+use serde::Serialize;
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use sqlx::SqlitePool;
+use tokio::{sync::Notify, task::JoinHandle};
+use tracing::{error, field, info, instrument, warn, Span};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    config::JobsConfig,
+    db::unix_now,
+    error::{ApiError, ErrorBody},
+    ledger::Requester,
+    telemetry::VERIFICATIONS,
+    verification::{parse_address, Claims, VerificationPayload},
+    webhooks::WebhookEvent,
+    AppState,
+};
+
+/// How often idle workers look for jobs whose retry is due.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, sqlx::Type, ToSchema)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub(crate) enum JobStatus {
+    /// Waiting for a worker, being processed, or waiting for a retry.
+    Pending,
+    /// Attestation is on-chain.
+    Confirmed,
+    /// Every attempt failed.
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow, ToSchema)]
+pub(crate) struct Job {
+    pub id: String,
+    /// Wallet being attested.
+    pub address: String,
+    pub status: JobStatus,
+    pub attempts: u32,
+    /// Transaction creating the attestation, or the last one that failed.
+    /// `null` once confirmed if the attestation already existed.
+    pub signature: Option<String>,
+    /// Why the last attempt failed.
+    pub error: Option<String>,
+    /// Unix seconds.
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+const JOB_COLUMNS: &str = "id, address, status, attempts, signature, error, created_at, updated_at";
+
+/// A job claimed by a worker.
+#[derive(Debug, sqlx::FromRow)]
+struct Claimed {
+    id: String,
+    address: String,
+    attempts: u32,
+    requester_ip: Option<String>,
+    user_agent: Option<String>,
+}
+
+pub(crate) struct JobQueue {
+    db: SqlitePool,
+    /// Wakes an idle worker when a job is enqueued.
+    enqueued: Notify,
+    workers: usize,
+    max_attempts: u32,
+    retry_delay: Duration,
+}
+
+impl JobQueue {
+    pub fn new(db: SqlitePool, config: &JobsConfig) -> Self {
+        Self {
+            db,
+            enqueued: Notify::new(),
+            workers: config.workers,
+            max_attempts: config.max_attempts,
+            retry_delay: config.retry_delay(),
+        }
+    }
+
+    /// Releases jobs claimed by workers of a previous run, which was stopped mid-job.
+    /// Their attestation might have landed, which the retry finds out before sending anything.
+    pub async fn recover(&self) -> Result<u64> {
+        let released = sqlx::query("UPDATE verification_jobs SET claimed = 0 WHERE claimed = 1")
+            .execute(&self.db)
+            .await?
+            .rows_affected();
+        Ok(released)
+    }
+
+    /// New pending job for `address`, or the one it already has.
+    pub async fn enqueue(&self, address: &Pubkey, requester: &Requester) -> Result<Job> {
+        let now = unix_now();
+        let inserted = sqlx::query_as::<_, Job>(&format!(
+            "INSERT INTO verification_jobs
+                (id, address, status, requester_ip, user_agent,
+                 next_attempt_at, created_at, updated_at)
+            VALUES (?1, ?2, 'pending', ?3, ?4, ?5, ?5, ?5)
+            ON CONFLICT (address) WHERE status = 'pending' DO NOTHING
+            RETURNING {JOB_COLUMNS}"
+        ))
+        .bind(Uuid::new_v4().to_string())
+        .bind(address.to_string())
+        .bind(&requester.ip)
+        .bind(&requester.user_agent)
+        .bind(now)
+        .fetch_optional(&self.db)
+        .await?;
+        if let Some(job) = inserted {
+            self.enqueued.notify_one();
+            return Ok(job);
+        }
+
+        sqlx::query_as(&format!(
+            "SELECT {JOB_COLUMNS} FROM verification_jobs WHERE address = ? AND status = 'pending'"
+        ))
+        .bind(address.to_string())
+        .fetch_one(&self.db)
+        .await
+        .context("pending job finished while enqueueing another")
+    }
+
+    pub async fn get(&self, id: &str) -> Result<Option<Job>> {
+        let job = sqlx::query_as(&format!(
+            "SELECT {JOB_COLUMNS} FROM verification_jobs WHERE id = ?"
+        ))
+        .bind(id)
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(job)
+    }
+
+    /// Oldest due job no other worker has, counting an attempt.
+    async fn claim(&self) -> Result<Option<Claimed>> {
+        let claimed = sqlx::query_as(
+            "UPDATE verification_jobs
+            SET claimed = 1, attempts = attempts + 1, updated_at = ?1
+            WHERE id = (
+                SELECT id FROM verification_jobs
+                WHERE status = 'pending' AND claimed = 0 AND next_attempt_at <= ?1
+                ORDER BY next_attempt_at
+                LIMIT 1
+            )
+            RETURNING id, address, attempts, requester_ip, user_agent",
+        )
+        .bind(unix_now())
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(claimed)
+    }
+
+    async fn confirm(&self, id: &str, signature: Option<Signature>) -> Result<()> {
+        sqlx::query(
+            "UPDATE verification_jobs
+            SET status = 'confirmed', claimed = 0, signature = ?2, error = NULL, updated_at = ?3
+            WHERE id = ?1",
+        )
+        .bind(id)
+        .bind(signature.map(|sig| sig.to_string()))
+        .bind(unix_now())
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Schedules a retry with exponential backoff, or fails the job after the last attempt.
+    /// Returns whether it failed for good.
+    async fn retry_or_fail(&self, job: &Claimed, err: &anyhow::Error) -> Result<bool> {
+        let exhausted = job.attempts >= self.max_attempts;
+        let backoff = self
+            .retry_delay
+            .saturating_mul(1 << job.attempts.saturating_sub(1).min(16))
+            .min(MAX_RETRY_DELAY);
+        let signature = err
+            .downcast_ref::<TransactionFailed>()
+            .map(|failed| failed.signature.to_string());
+        let now = unix_now();
+        sqlx::query(
+            "UPDATE verification_jobs
+            SET status = ?2, claimed = 0, signature = COALESCE(?3, signature), error = ?4,
+                next_attempt_at = ?5, updated_at = ?6
+            WHERE id = ?1",
+        )
+        .bind(&job.id)
+        .bind(if exhausted {
+            JobStatus::Failed
+        } else {
+            JobStatus::Pending
+        })
+        .bind(signature)
+        .bind(format!("{err:#}"))
+        .bind(now + backoff.as_secs() as i64)
+        .bind(now)
+        .execute(&self.db)
+        .await?;
+        Ok(exhausted)
+    }
+}
+
+/// Spawns the configured number of workers, which stop claiming jobs on shutdown
+/// and finish once their current job is done.
+pub(crate) fn spawn_workers(state: &Arc<AppState>) -> Vec<JoinHandle<()>> {
+    (0..state.jobs.workers)
+        .map(|_| {
+            let state = Arc::clone(state);
+            tokio::spawn(async move { work(&state).await })
+        })
+        .collect()
+}
+
+async fn work(state: &AppState) {
+    while !state.is_shutting_down() {
+        match state.jobs.claim().await {
+            Ok(Some(job)) => process(state, job).await,
+            Ok(None) => tokio::select! {
+                _ = tokio::time::timeout(POLL_INTERVAL, state.jobs.enqueued.notified()) => {}
+                _ = state.shutting_down() => {}
+            },
+            Err(err) => {
+                error!(%err, "couldn't claim verification job");
+                tokio::select! {
+                    _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                    _ = state.shutting_down() => {}
+                }
+            }
+        }
+    }
+}
+
+#[instrument(name = "verification.job", skip_all, fields(job = %job.id, pubkey = %job.address, attempt = job.attempts))]
+async fn process(state: &AppState, job: Claimed) {
+    let stored = match issue(state, &job.address).await {
+        Ok(issued) => {
+            let status = if issued.is_some() {
+                "created"
+            } else {
+                "existing"
+            };
+            counter!(VERIFICATIONS, "status" => status).increment(1);
+            info!(status, "verification job confirmed");
+            if let Some((user, issued)) = &issued {
+                let requester = Requester {
+                    ip: job.requester_ip.clone(),
+                    user_agent: job.user_agent.clone(),
+                };
+                let recorded = state
+                    .ledger
+                    .record_issued(
+                        user,
+                        &Claims::VERIFIED.into(),
+                        issued,
+                        &requester,
+                        Some(&job.id),
+                    )
+                    .await;
+                if let Err(err) = recorded {
+                    error!(%err, "couldn't record attestation in the ledger");
+                }
+                state
+                    .webhooks
+                    .emit(WebhookEvent::created(user, issued))
+                    .await;
+            }
+            let signature = issued.map(|(_, issued)| issued.signature);
+            state.jobs.confirm(&job.id, signature).await
+        }
+        Err(err) => {
+            warn!(%err, "verification job attempt failed");
+            state.jobs.retry_or_fail(&job, &err).await.map(|exhausted| {
+                if exhausted {
+                    counter!(VERIFICATIONS, "status" => "failed").increment(1);
+                    warn!("verification job failed, no attempts left");
+                }
+            })
+        }
+    };
+    if let Err(err) = stored {
+        error!(%err, "couldn't update verification job");
+    }
+}
+
+/// Creates the attestation unless it's already on-chain, which is how
+/// an attempt interrupted after sending its transaction is told apart.
+/// `None` if it already was, or was created concurrently.
+async fn issue(state: &AppState, address: &str) -> Result<Option<(Pubkey, IssuedAttestation)>> {
+    let user = Pubkey::from_str(address)?;
+    if state.sas.fetch_attestation(user).await?.is_some() {
+        return Ok(None);
+    }
+    let _permit = state.limits.wait_for_transaction_permit().await;
+    match state
+        .sas
+        .create_attestation(user, Claims::VERIFIED.into())
+        .await
+    {
+        Ok(issued) => Ok(Some((user, issued))),
+        Err(err) if err.is::<AttestationExists>() => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Enqueues a verification job and responds with `202` right away.
+/// A wallet that has a pending job gets that one back.
+#[utoipa::path(
+    post,
+    path = "/verification/jobs",
+    tag = "verification",
+    request_body = VerificationPayload,
+    responses(
+        (status = 202, description = "Job was enqueued, see its `Location`", body = Job,
+            headers(("Location" = String, description = "URL of the job"))),
+        (status = 400, description = "Malformed request", body = ErrorBody),
+        (status = 429, description = "Rate limited", body = ErrorBody),
+        (status = 503, description = "Database unreachable", body = ErrorBody),
+    )
+)]
+#[instrument(skip_all, fields(pubkey = field::Empty))]
+pub(crate) async fn enqueue_handler(
+    Extension(requester): Extension<Requester>,
+    payload: Result<Json<VerificationPayload>, JsonRejection>,
+    state: Arc<AppState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let Json(payload) = payload?;
+    Span::current().record("pubkey", &payload.address);
+    let user_pubkey = parse_address(&payload.address)?;
+    state.limits.check_wallet(&user_pubkey)?;
+
+    let job = state
+        .jobs
+        .enqueue(&user_pubkey, &requester)
+        .await
+        .map_err(|err| ApiError::database(&err, "couldn't enqueue verification job"))?;
+    let location = format!("/verification/jobs/{}", job.id);
+    Ok((
+        StatusCode::ACCEPTED,
+        [(header::LOCATION, location)],
+        Json(job),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/verification/jobs/{id}",
+    tag = "verification",
+    params(("id" = String, Path, description = "Job id")),
+    responses(
+        (status = 200, body = Job),
+        (status = 404, description = "Unknown job", body = ErrorBody),
+        (status = 503, description = "Database unreachable", body = ErrorBody),
+    )
+)]
+pub(crate) async fn job_handler(
+    id: Result<Path<String>, PathRejection>,
+    state: Arc<AppState>,
+) -> Result<Json<Job>, ApiError> {
+    let Path(id) = id?;
+    let job = state
+        .jobs
+        .get(&id)
+        .await
+        .map_err(|err| ApiError::database(&err, "couldn't read verification job"))?;
+    job.map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("no verification job {id:?}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use sas_client::{AttestationService, Cluster};
+    use solana_sdk::signature::Keypair;
+
+    use super::*;
+    use crate::{config::Config, db};
+
+    #[tokio::test]
+    async fn test_idle_workers_stop_on_shutdown() {
+        let sas = AttestationService::new(
+            Cluster::Localnet,
+            Arc::new(Keypair::new()),
+            Arc::new(Keypair::new()),
+            Arc::new(Keypair::new()),
+        );
+        let db = db::connect("sqlite::memory:").await.unwrap();
+        let state = Arc::new(AppState::new(sas, db, &Config::default()).unwrap());
+
+        let workers = spawn_workers(&state);
+        assert!(!workers.is_empty());
+        tokio::task::yield_now().await;
+        assert!(workers.iter().all(|worker| !worker.is_finished()));
+
+        state.shutdown.send_replace(true);
+        let stopped =
+            tokio::time::timeout(POLL_INTERVAL / 2, futures_util::future::join_all(workers));
+        assert!(stopped.await.unwrap().iter().all(Result::is_ok));
+    }
+}
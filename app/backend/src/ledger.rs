@@ -0,0 +1,352 @@
+//! Issuance ledger: every attestation the backend issued or revoked, and who asked for it.
+//! [`reconcile`] compares it against what is on-chain.
+
+use std::{collections::HashMap, net::IpAddr};
+
+use anyhow::Result;
+use axum::http::{header, HeaderMap};
+use sas_client::{AttestationAccount, AttestationPayload, IssuedAttestation, RevokedAttestation};
+use serde::Serialize;
+use solana_sdk::pubkey::Pubkey;
+use sqlx::SqlitePool;
+
+use crate::db::unix_now;
+
+/// Client a request came from, put into request extensions by [`crate::limits::limit_per_ip`].
+#[derive(Debug, Clone)]
+pub(crate) struct Requester {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl Requester {
+    pub fn new(ip: IpAddr, headers: &HeaderMap) -> Self {
+        Self {
+            ip: Some(ip.to_string()),
+            user_agent: headers
+                .get(header::USER_AGENT)
+                .and_then(|agent| agent.to_str().ok())
+                .map(str::to_string),
+        }
+    }
+}
+
+/// An attestation the ledger considers active: issued, and not revoked since.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub(crate) struct LedgerEntry {
+    pub pda: String,
+    pub address: String,
+    pub age: bool,
+    pub country: bool,
+    pub expiry: i64,
+    pub signature: String,
+    pub slot: i64,
+}
+
+pub(crate) struct Ledger {
+    db: SqlitePool,
+}
+
+impl Ledger {
+    pub fn new(db: SqlitePool) -> Self {
+        Self { db }
+    }
+
+    pub async fn record_issued(
+        &self,
+        user: &Pubkey,
+        payload: &AttestationPayload,
+        issued: &IssuedAttestation,
+        requester: &Requester,
+        job_id: Option<&str>,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO attestations
+                (pda, address, age, country, expiry, signature, slot,
+                 requester_ip, user_agent, job_id, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(issued.pda.to_string())
+        .bind(user.to_string())
+        .bind(payload.age)
+        .bind(payload.country)
+        .bind(issued.expiry)
+        .bind(issued.signature.to_string())
+        .bind(issued.slot as i64)
+        .bind(&requester.ip)
+        .bind(&requester.user_agent)
+        .bind(job_id)
+        .bind(unix_now())
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn record_revoked(
+        &self,
+        user: &Pubkey,
+        revoked: &RevokedAttestation,
+        reason: Option<&str>,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO revocations (pda, address, signature, slot, reason, created_at)
+            VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(revoked.pda.to_string())
+        .bind(user.to_string())
+        .bind(revoked.signature.to_string())
+        .bind(revoked.slot as i64)
+        .bind(reason)
+        .bind(unix_now())
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Latest issuance of every PDA that has no revocation after it.
+    pub async fn active(&self) -> Result<Vec<LedgerEntry>> {
+        self.active_expiring(i64::MIN, i64::MAX).await
+    }
+
+    /// Active entries with `after < expiry <= until`, soonest first.
+    pub async fn active_expiring(&self, after: i64, until: i64) -> Result<Vec<LedgerEntry>> {
+        let entries = sqlx::query_as(
+            "SELECT pda, address, age, country, expiry, signature, slot
+            FROM attestations AS a
+            WHERE expiry > ? AND expiry <= ?
+            AND NOT EXISTS (
+                SELECT 1 FROM attestations AS later WHERE later.pda = a.pda AND later.slot > a.slot
+            )
+            AND NOT EXISTS (
+                SELECT 1 FROM revocations AS r WHERE r.pda = a.pda AND r.slot >= a.slot
+            )
+            ORDER BY expiry, slot",
+        )
+        .bind(after)
+        .bind(until)
+        .fetch_all(&self.db)
+        .await?;
+        Ok(entries)
+    }
+
+    /// Records that the attestation at `pda` was reminded about, before it expires at `expiry`.
+    /// Returns whether it's the first reminder.
+    pub async fn record_reminder(&self, pda: &str, expiry: i64) -> Result<bool> {
+        let inserted = sqlx::query(
+            "INSERT INTO expiry_reminders (pda, expiry, created_at) VALUES (?, ?, ?)
+            ON CONFLICT (pda, expiry) DO NOTHING",
+        )
+        .bind(pda)
+        .bind(expiry)
+        .bind(unix_now())
+        .execute(&self.db)
+        .await?
+        .rows_affected();
+        Ok(inserted > 0)
+    }
+}
+
+/// Differences between the ledger and the chain. Empty if they agree.
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct Reconciliation {
+    pub on_chain: usize,
+    pub in_ledger: usize,
+    /// Active in the ledger, but closed on-chain by someone else.
+    pub missing_on_chain: Vec<LedgerEntry>,
+    /// On-chain, but never recorded, e.g. user-paid attestations or failed ledger writes.
+    pub unrecorded: Vec<OnChain>,
+    /// Recorded with a different payload or expiry than the account has.
+    pub mismatched: Vec<Mismatch>,
+}
+
+impl Reconciliation {
+    pub fn is_consistent(&self) -> bool {
+        self.missing_on_chain.is_empty() && self.unrecorded.is_empty() && self.mismatched.is_empty()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct OnChain {
+    pub pda: String,
+    pub address: String,
+    pub age: bool,
+    pub country: bool,
+    pub expiry: i64,
+}
+
+impl From<&AttestationAccount> for OnChain {
+    fn from(account: &AttestationAccount) -> Self {
+        Self {
+            pda: account.pda.to_string(),
+            address: account.user.to_string(),
+            age: account.payload.age,
+            country: account.payload.country,
+            expiry: account.expiry,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Mismatch {
+    pub ledger: LedgerEntry,
+    pub on_chain: OnChain,
+}
+
+/// Matches active ledger entries with on-chain accounts by PDA.
+pub(crate) fn reconcile(
+    ledger: Vec<LedgerEntry>,
+    accounts: &[AttestationAccount],
+) -> Reconciliation {
+    let mut report = Reconciliation {
+        on_chain: accounts.len(),
+        in_ledger: ledger.len(),
+        ..Reconciliation::default()
+    };
+    let mut recorded: HashMap<String, LedgerEntry> = ledger
+        .into_iter()
+        .map(|entry| (entry.pda.clone(), entry))
+        .collect();
+
+    for account in accounts {
+        let on_chain = OnChain::from(account);
+        match recorded.remove(&on_chain.pda) {
+            None => report.unrecorded.push(on_chain),
+            Some(entry)
+                if entry.age != on_chain.age
+                    || entry.country != on_chain.country
+                    || entry.expiry != on_chain.expiry =>
+            {
+                report.mismatched.push(Mismatch {
+                    ledger: entry,
+                    on_chain,
+                });
+            }
+            Some(_) => {}
+        }
+    }
+    report.missing_on_chain = recorded.into_values().collect();
+    report.missing_on_chain.sort_by_key(|entry| entry.slot);
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::signature::Signature;
+
+    use super::*;
+
+    const PAYLOAD: AttestationPayload = AttestationPayload {
+        age: true,
+        country: true,
+    };
+
+    async fn ledger() -> Ledger {
+        Ledger::new(crate::db::connect("sqlite::memory:").await.unwrap())
+    }
+
+    async fn issue(ledger: &Ledger, user: &Pubkey, pda: Pubkey, slot: u64) {
+        let issued = IssuedAttestation {
+            pda,
+            signature: Signature::new_unique(),
+            slot,
+            expiry: 1000 + slot as i64,
+        };
+        let requester = Requester {
+            ip: None,
+            user_agent: None,
+        };
+        ledger
+            .record_issued(user, &PAYLOAD, &issued, &requester, None)
+            .await
+            .unwrap();
+    }
+
+    async fn revoke(ledger: &Ledger, user: &Pubkey, pda: Pubkey, slot: u64) {
+        let revoked = RevokedAttestation {
+            pda,
+            signature: Signature::new_unique(),
+            slot,
+        };
+        ledger.record_revoked(user, &revoked, None).await.unwrap();
+    }
+
+    fn entry(pda: Pubkey, slot: i64) -> LedgerEntry {
+        LedgerEntry {
+            pda: pda.to_string(),
+            address: Pubkey::new_unique().to_string(),
+            age: true,
+            country: true,
+            expiry: 1000 + slot,
+            signature: Signature::new_unique().to_string(),
+            slot,
+        }
+    }
+
+    fn account(entry: &LedgerEntry) -> AttestationAccount {
+        AttestationAccount {
+            pda: entry.pda.parse().unwrap(),
+            user: entry.address.parse().unwrap(),
+            payload: PAYLOAD,
+            expiry: entry.expiry,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_active_is_latest_issuance_not_revoked_since() {
+        let ledger = ledger().await;
+        let user = Pubkey::new_unique();
+        let (reissued, revoked, active) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+
+        // Revoked, then attested again: only the latest issuance is active.
+        issue(&ledger, &user, reissued, 10).await;
+        revoke(&ledger, &user, reissued, 20).await;
+        issue(&ledger, &user, reissued, 30).await;
+        issue(&ledger, &user, revoked, 11).await;
+        revoke(&ledger, &user, revoked, 21).await;
+        issue(&ledger, &user, active, 12).await;
+
+        let entries = ledger.active().await.unwrap();
+        let active_at: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.pda.clone(), entry.slot))
+            .collect();
+        assert_eq!(
+            active_at,
+            vec![(active.to_string(), 12), (reissued.to_string(), 30)]
+        );
+
+        let expiring = ledger.active_expiring(1012, 2000).await.unwrap();
+        assert_eq!(expiring.len(), 1);
+        assert_eq!(expiring[0].pda, reissued.to_string());
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let matching = entry(Pubkey::new_unique(), 1);
+        let closed = entry(Pubkey::new_unique(), 2);
+        let mismatched = entry(Pubkey::new_unique(), 3);
+        let mut extended = account(&mismatched);
+        extended.expiry += 1;
+        let unrecorded = account(&entry(Pubkey::new_unique(), 4));
+
+        let report = reconcile(
+            vec![matching.clone(), closed.clone(), mismatched.clone()],
+            &[account(&matching), extended, unrecorded.clone()],
+        );
+        assert!(!report.is_consistent());
+        assert_eq!((report.in_ledger, report.on_chain), (3, 3));
+        assert_eq!(report.missing_on_chain.len(), 1);
+        assert_eq!(report.missing_on_chain[0].pda, closed.pda);
+        assert_eq!(report.unrecorded.len(), 1);
+        assert_eq!(report.unrecorded[0].pda, unrecorded.pda.to_string());
+        assert_eq!(report.mismatched.len(), 1);
+        assert_eq!(report.mismatched[0].ledger.pda, mismatched.pda);
+
+        let report = reconcile(vec![matching.clone()], &[account(&matching)]);
+        assert!(report.is_consistent());
+    }
+}
@@ -0,0 +1,277 @@
+use std::{
+    hash::Hash,
+    net::{IpAddr, SocketAddr},
+    num::NonZeroU32,
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use governor::{
+    clock::{Clock, DefaultClock},
+    DefaultKeyedRateLimiter, Quota,
+};
+use sas_client::{SpendCap, SpendCapReached};
+use solana_sdk::pubkey::Pubkey;
+use sqlx::SqlitePool;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, SemaphorePermit};
+use tracing::warn;
+
+use crate::{config::LimitsConfig, db::unix_now, error::ApiError, ledger::Requester, AppState};
+
+const SECS_PER_DAY: i64 = 60 * 60 * 24;
+
+/// Token buckets per client IP and per wallet, and caps on transactions in flight
+/// and open event streams.
+/// A zero rate disables the respective bucket.
+pub(crate) struct Limits {
+    per_ip: Option<DefaultKeyedRateLimiter<IpAddr>>,
+    per_wallet: Option<DefaultKeyedRateLimiter<Pubkey>>,
+    transactions: Semaphore,
+    event_streams: Arc<Semaphore>,
+    trust_forwarded_for: bool,
+}
+
+impl Limits {
+    pub fn new(config: &LimitsConfig) -> Self {
+        let keyed = |per_minute| NonZeroU32::new(per_minute).map(Quota::per_minute);
+        Self {
+            per_ip: keyed(config.per_ip_per_minute).map(DefaultKeyedRateLimiter::keyed),
+            per_wallet: keyed(config.per_wallet_per_minute).map(DefaultKeyedRateLimiter::keyed),
+            transactions: Semaphore::new(config.max_concurrent_transactions),
+            event_streams: Arc::new(Semaphore::new(config.max_event_streams)),
+            trust_forwarded_for: config.trust_forwarded_for,
+        }
+    }
+
+    pub fn check_wallet(&self, wallet: &Pubkey) -> Result<(), ApiError> {
+        check(&self.per_wallet, wallet, "wallet")
+    }
+
+    /// Has to be held while sending a transaction paid by payer.
+    pub fn transaction_permit(&self) -> Result<SemaphorePermit<'_>, ApiError> {
+        self.transactions
+            .try_acquire()
+            .map_err(|_| ApiError::RateLimited {
+                limit: "transactions",
+                message: "too many transactions in flight".to_string(),
+                retry_after: Duration::from_secs(1),
+            })
+    }
+
+    /// Has to be held by an open `/attestations/events` stream.
+    pub fn event_stream_permit(&self) -> Result<OwnedSemaphorePermit, ApiError> {
+        Arc::clone(&self.event_streams)
+            .try_acquire_owned()
+            .map_err(|_| ApiError::RateLimited {
+                limit: "event_streams",
+                message: "too many event streams open".to_string(),
+                retry_after: Duration::from_secs(5),
+            })
+    }
+
+    /// Waits for a [`Self::transaction_permit`], for background work that has no client to reject.
+    pub async fn wait_for_transaction_permit(&self) -> SemaphorePermit<'_> {
+        self.transactions
+            .acquire()
+            .await
+            .expect("transaction semaphore is never closed")
+    }
+
+    /// Forgets buckets that are full again, so that memory doesn't grow with every new key.
+    pub fn retain_recent(&self) {
+        if let Some(limiter) = &self.per_ip {
+            limiter.retain_recent();
+        }
+        if let Some(limiter) = &self.per_wallet {
+            limiter.retain_recent();
+        }
+    }
+
+    /// The first `X-Forwarded-For` address if trusted and present, or the peer address.
+    fn client_ip(&self, headers: &HeaderMap, peer: SocketAddr) -> IpAddr {
+        let forwarded = self
+            .trust_forwarded_for
+            .then(|| headers.get("x-forwarded-for")?.to_str().ok())
+            .flatten()
+            .and_then(|value| value.split(',').next()?.trim().parse().ok());
+        forwarded.unwrap_or(peer.ip())
+    }
+}
+
+fn check<K: Hash + Eq + Clone>(
+    limiter: &Option<DefaultKeyedRateLimiter<K>>,
+    key: &K,
+    limit: &'static str,
+) -> Result<(), ApiError> {
+    let Some(limiter) = limiter else {
+        return Ok(());
+    };
+    limiter
+        .check_key(key)
+        .map_err(|not_until| ApiError::RateLimited {
+            limit,
+            message: format!("too many requests for this {limit}"),
+            retry_after: not_until.wait_time_from(DefaultClock::default().now()),
+        })
+}
+
+/// Route middleware applying the per-IP bucket.
+/// Passes the client on to handlers as a [`Requester`] extension.
+pub(crate) async fn limit_per_ip(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let ip = state.limits.client_ip(request.headers(), peer);
+    if let Err(err) = check(&state.limits.per_ip, &ip, "ip") {
+        warn!(%ip, "rate limited");
+        return err.into_response();
+    }
+    let requester = Requester::new(ip, request.headers());
+    request.extensions_mut().insert(requester);
+    next.run(request).await
+}
+
+/// Daily cap on payer spend, counted in the database so that restarts don't reset it,
+/// and shared with commands run against the same database.
+pub(crate) struct DailySpendCap {
+    db: SqlitePool,
+    cap: u64,
+}
+
+impl DailySpendCap {
+    pub fn new(db: SqlitePool, cap: u64) -> Self {
+        Self { db, cap }
+    }
+
+    /// Adds `lamports` to the spend of `day` unless that goes over the cap, in one statement,
+    /// so that concurrent reservations can't all pass the check.
+    async fn reserve_on(&self, day: i64, lamports: u64) -> Result<()> {
+        let reserved = sqlx::query(
+            "INSERT INTO payer_spend (day, lamports) SELECT ?1, ?2 WHERE ?2 <= ?3
+            ON CONFLICT (day) DO UPDATE SET lamports = lamports + excluded.lamports
+            WHERE lamports + excluded.lamports <= ?3",
+        )
+        .bind(day)
+        .bind(lamports as i64)
+        .bind(self.cap as i64)
+        .execute(&self.db)
+        .await?
+        .rows_affected();
+        if reserved == 0 {
+            let spent = self.spent_on(day).await?;
+            return Err(SpendCapReached::today(spent, self.cap).into());
+        }
+        Ok(())
+    }
+
+    async fn release_on(&self, day: i64, lamports: u64) -> Result<()> {
+        sqlx::query("UPDATE payer_spend SET lamports = max(lamports - ?2, 0) WHERE day = ?1")
+            .bind(day)
+            .bind(lamports as i64)
+            .execute(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    async fn spent_on(&self, day: i64) -> Result<u64> {
+        let spent: Option<i64> =
+            sqlx::query_scalar("SELECT lamports FROM payer_spend WHERE day = ?")
+                .bind(day)
+                .fetch_optional(&self.db)
+                .await?;
+        Ok(spent.unwrap_or_default() as u64)
+    }
+}
+
+#[async_trait]
+impl SpendCap for DailySpendCap {
+    async fn reserve(&self, lamports: u64) -> Result<()> {
+        self.reserve_on(unix_now() / SECS_PER_DAY, lamports).await
+    }
+
+    async fn release(&self, lamports: u64) -> Result<()> {
+        self.release_on(unix_now() / SECS_PER_DAY, lamports).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(per_wallet_per_minute: u32) -> LimitsConfig {
+        LimitsConfig {
+            per_ip_per_minute: 0,
+            per_wallet_per_minute,
+            max_concurrent_transactions: 1,
+            max_event_streams: 1,
+            trust_forwarded_for: false,
+        }
+    }
+
+    #[test]
+    fn test_wallet_bucket() {
+        let limits = Limits::new(&config(2));
+        let wallet = Pubkey::new_unique();
+        assert!(limits.check_wallet(&wallet).is_ok());
+        assert!(limits.check_wallet(&wallet).is_ok());
+        assert!(matches!(
+            limits.check_wallet(&wallet),
+            Err(ApiError::RateLimited {
+                limit: "wallet",
+                ..
+            })
+        ));
+        assert!(limits.check_wallet(&Pubkey::new_unique()).is_ok());
+
+        let unlimited = Limits::new(&config(0));
+        for _ in 0..10 {
+            assert!(unlimited.check_wallet(&wallet).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_permits() {
+        let limits = Limits::new(&config(0));
+        let permit = limits.transaction_permit().unwrap();
+        assert!(limits.transaction_permit().is_err());
+        drop(permit);
+        assert!(limits.transaction_permit().is_ok());
+
+        let stream = limits.event_stream_permit().unwrap();
+        assert!(limits.event_stream_permit().is_err());
+        drop(stream);
+        assert!(limits.event_stream_permit().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_daily_spend_cap_rollover() {
+        let db = crate::db::connect("sqlite::memory:").await.unwrap();
+        let cap = DailySpendCap::new(db, 10_000);
+
+        cap.reserve_on(1, 6_000).await.unwrap();
+        cap.reserve_on(1, 4_000).await.unwrap();
+        let err = cap.reserve_on(1, 1).await.unwrap_err();
+        let reached = err.downcast_ref::<SpendCapReached>().unwrap();
+        assert_eq!((reached.spent, reached.cap), (10_000, 10_000));
+
+        // A new day starts from zero, and never takes more than the cap at once.
+        assert!(cap.reserve_on(2, 10_001).await.is_err());
+        cap.reserve_on(2, 10_000).await.unwrap();
+        assert_eq!(cap.spent_on(1).await.unwrap(), 10_000);
+
+        cap.release_on(1, 5_000).await.unwrap();
+        cap.reserve_on(1, 5_000).await.unwrap();
+        cap.release_on(1, 20_000).await.unwrap();
+        assert_eq!(cap.spent_on(1).await.unwrap(), 0);
+    }
+}
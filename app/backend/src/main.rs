@@ -1,15 +1,26 @@
-use std::error::Error;
-use std::sync::Arc;
+use std::{future::Future, net::SocketAddr, process::ExitCode, sync::Arc, time::Duration};
 
 use anchor_client::{Client, Cluster, Program};
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
 use axum::{
-    routing::{get, post},
-    Router,
+    middleware,
+    routing::{get, post, MethodRouter},
+    Json, Router,
 };
-use sas_client::AttestationService;
-use solana_sdk::signature::{read_keypair_file, Keypair};
-use tokio::net::TcpListener;
+use config::Config;
+use events::AttestationEvents;
+use futures_util::future::join_all;
+use indexer::Validations;
+use jobs::JobQueue;
+use ledger::Ledger;
+use limits::{DailySpendCap, Limits};
+use openapi::ApiDoc;
+use sas_client::{AttestationService, BalanceMonitorConfig, TopUp};
+use single_flight::SingleFlight;
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Keypair};
+use sqlx::SqlitePool;
+use tokio::{net::TcpListener, signal, sync::watch, task::JoinHandle};
+use tracing::{error, info, warn};
 use tracing_appender::{non_blocking::WorkerGuard, rolling};
 use tracing_error::ErrorLayer;
 use tracing_subscriber::{
@@ -18,35 +29,175 @@ use tracing_subscriber::{
     util::SubscriberInitExt,
     EnvFilter,
 };
+use utoipa::OpenApi;
+use verification::Verification;
+use webhooks::Webhooks;
 
+mod commands;
+mod config;
+mod db;
+mod error;
+mod events;
+mod expiry;
+mod health;
+mod indexer;
+mod jobs;
+mod ledger;
+mod limits;
+mod openapi;
+#[cfg(feature = "otel")]
+mod otel;
+mod single_flight;
+mod telemetry;
 mod validate;
 mod verification;
+mod webhooks;
+
+const LIMITER_CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
 
 pub(crate) struct AppState {
     pub sas: AttestationService,
     pub validate_program: Program<Arc<Keypair>>,
+    pub limits: Limits,
+    pub jobs: JobQueue,
+    pub ledger: Ledger,
+    pub validations: Validations,
+    pub webhooks: Webhooks,
+    pub verifications: SingleFlight<Pubkey, Verification>,
+    pub events: AttestationEvents,
+    /// Set once the process starts shutting down.
+    pub shutdown: watch::Sender<bool>,
 }
 
 impl AppState {
-    pub fn try_from_env(sas: AttestationService) -> std::result::Result<Self, Box<dyn Error>> {
-        let payer = read_keypair_file(std::env::var("PAYER_CREDS")?)?;
-        let client = Client::new(
-            if std::env::var("CLUSTER").is_ok_and(|cluster| cluster == "devnet") {
-                Cluster::Devnet
-            } else {
-                Cluster::Localnet
-            },
-            Arc::new(payer),
+    pub fn new(sas: AttestationService, db: SqlitePool, config: &Config) -> Result<Self> {
+        let cluster = Cluster::Custom(
+            sas.cluster().rpc_url().to_string(),
+            sas.cluster().ws_url().to_string(),
         );
-        let program = client.program(test_solana_program::ID)?;
+        let commitment = CommitmentConfig {
+            commitment: config.solana.commitment,
+        };
+        let client = Client::new_with_options(cluster, sas.payer(), commitment);
+        let program = client.program(config.programs.validate)?;
         Ok(Self {
             sas,
             validate_program: program,
+            limits: Limits::new(&config.limits),
+            jobs: JobQueue::new(db.clone(), &config.jobs),
+            ledger: Ledger::new(db.clone()),
+            validations: Validations::new(db.clone()),
+            webhooks: Webhooks::new(db, &config.webhooks)?,
+            verifications: SingleFlight::new(),
+            events: AttestationEvents::new(),
+            shutdown: watch::channel(false).0,
         })
     }
+
+    pub fn is_shutting_down(&self) -> bool {
+        *self.shutdown.borrow()
+    }
+
+    /// Resolves once the process starts shutting down.
+    pub fn shutting_down(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut shutdown = self.shutdown.subscribe();
+        async move {
+            _ = shutdown.wait_for(|&shutdown| shutdown).await;
+        }
+    }
+}
+
+/// API routes, which are rate limited and tracked, and documented in [`ApiDoc`].
+fn api_routes(state: &Arc<AppState>) -> Vec<(&'static str, MethodRouter)> {
+    vec![
+        (
+            "/verification",
+            post({
+                let state = Arc::clone(state);
+                move |requester, payload| {
+                    verification::verification_handler(requester, payload, state)
+                }
+            }),
+        ),
+        (
+            "/verification/jobs",
+            post({
+                let state = Arc::clone(state);
+                move |requester, payload| jobs::enqueue_handler(requester, payload, state)
+            }),
+        ),
+        (
+            "/verification/jobs/{id}",
+            get({
+                let state = Arc::clone(state);
+                move |id| jobs::job_handler(id, state)
+            }),
+        ),
+        (
+            "/verification/transaction",
+            post({
+                let state = Arc::clone(state);
+                move |payload| verification::user_paid_verification_handler(payload, state)
+            }),
+        ),
+        (
+            "/validations",
+            get({
+                let state = Arc::clone(state);
+                move |query| indexer::history_handler(query, state)
+            }),
+        ),
+        (
+            "/attestations/events",
+            get({
+                let state = Arc::clone(state);
+                move |query| events::events_handler(query, state)
+            }),
+        ),
+        (
+            "/validate",
+            get({
+                let state = Arc::clone(state);
+                move |payload| validate::validate_handler(payload, state)
+            }),
+        ),
+    ]
+}
+
+/// Top-ups are made from the configured treasury if there's one,
+/// otherwise airdropped on test clusters.
+fn balance_monitor_config(
+    config: &Config,
+    sas: &AttestationService,
+) -> Result<BalanceMonitorConfig> {
+    let mut monitor = config.solana.balance_monitor()?;
+    if matches!(monitor.top_up, TopUp::Disabled) && sas.cluster().is_test_cluster() {
+        monitor.top_up = TopUp::Airdrop;
+    }
+    Ok(monitor)
+}
+
+/// Flushes logs, and exported traces if there are any, when dropped.
+struct TracingGuard {
+    _stdout: WorkerGuard,
+    _file: WorkerGuard,
+    #[cfg(feature = "otel")]
+    tracer_provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
 }
 
-fn init_tracing() -> (WorkerGuard, WorkerGuard) {
+#[cfg(feature = "otel")]
+impl Drop for TracingGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.tracer_provider.take() {
+            // Log writers are guarded by fields, which are only dropped after this.
+            if let Err(err) = provider.shutdown() {
+                error!(%err, "couldn't flush traces");
+            }
+        }
+    }
+}
+
+fn init_tracing() -> TracingGuard {
     let (stdout_writer, stdout_guard) = tracing_appender::non_blocking(std::io::stdout());
 
     let file_appender = rolling::daily("logs", "app.jsonl");
@@ -70,43 +221,261 @@ fn init_tracing() -> (WorkerGuard, WorkerGuard) {
         .with_span_events(FmtSpan::CLOSE)
         .json();
 
-    tracing_subscriber::registry()
+    let registry = tracing_subscriber::registry()
         .with(env_filter)
         .with(ErrorLayer::default())
         .with(pretty_stdout)
-        .with(json_file)
-        .init();
+        .with(json_file);
 
-    (stdout_guard, file_guard)
+    #[cfg(feature = "otel")]
+    {
+        let (tracer_provider, otel_err) = match otel::init_tracer_provider() {
+            Ok(provider) => (provider, None),
+            Err(err) => (None, Some(err)),
+        };
+        registry
+            .with(tracer_provider.as_ref().map(otel::layer))
+            .init();
+        if let Some(err) = otel_err {
+            error!("couldn't set up trace export: {err:#}");
+        }
+        TracingGuard {
+            _stdout: stdout_guard,
+            _file: file_guard,
+            tracer_provider,
+        }
+    }
+    #[cfg(not(feature = "otel"))]
+    {
+        registry.init();
+        TracingGuard {
+            _stdout: stdout_guard,
+            _file: file_guard,
+        }
+    }
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    dotenvy::dotenv()?;
-    let _tracing_guards = init_tracing();
-
-    let shared_state = {
-        let mut sas = AttestationService::try_from_env().unwrap();
-        sas.init().await.unwrap();
-        Arc::new(AppState::try_from_env(sas).unwrap())
+/// Resolves on the first SIGINT (Ctrl+C) or SIGTERM.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(err) = signal::ctrl_c().await {
+            error!(%err, "couldn't listen for SIGINT");
+            std::future::pending::<()>().await;
+        }
     };
-    let app = Router::new()
-        .route(
-            "/verification",
-            post({
-                let state = Arc::clone(&shared_state);
-                move |payload| verification::verification_handler(payload, state)
-            }),
-        )
+    #[cfg(unix)]
+    let terminate = async {
+        match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => _ = sigterm.recv().await,
+            Err(err) => {
+                error!(%err, "couldn't listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => info!("received SIGINT"),
+        _ = terminate => info!("received SIGTERM"),
+    }
+}
+
+/// Serves `app` until a shutdown signal, which sets `shutdown`, then waits up to
+/// `drain_timeout` for in-flight requests and `workers` (and the transactions they send)
+/// to finish.
+async fn serve(
+    listener: TcpListener,
+    app: Router,
+    shutdown_tx: watch::Sender<bool>,
+    workers: Vec<JoinHandle<()>>,
+    drain_timeout: Duration,
+) -> Result<()> {
+    let shutdown_rx = shutdown_tx.subscribe();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        shutdown_tx.send_replace(true);
+    });
+    let shutdown = |mut rx: watch::Receiver<bool>| async move {
+        _ = rx.wait_for(|&shutdown| shutdown).await;
+    };
+
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+    let server = axum::serve(listener, app).with_graceful_shutdown(shutdown(shutdown_rx.clone()));
+    // The server only returns once shutdown starts, unless it fails.
+    let drained = async {
+        server.await.context("server failed")?;
+        join_all(workers).await;
+        anyhow::Ok(())
+    };
+    tokio::select! {
+        res = drained => res?,
+        _ = async {
+            shutdown(shutdown_rx).await;
+            info!(timeout = ?drain_timeout, "draining in-flight requests and jobs");
+            tokio::time::sleep(drain_timeout).await;
+        } => warn!("in-flight requests and jobs didn't finish in time, dropping them"),
+    }
+    info!("shut down");
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let dotenv = dotenvy::dotenv();
+    // Guards flush the logs when dropped, so they have to outlive any error below.
+    let _tracing_guard = init_tracing();
+    if let Err(err) = dotenv {
+        if !err.not_found() {
+            error!(%err, "couldn't load .env");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            error!("{err:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => run_server().await,
+        ["reconcile"] => commands::reconcile().await,
+        ["revoke", address] => commands::revoke(address, None).await,
+        ["revoke", address, reason] => commands::revoke(address, Some(reason)).await,
+        ["dead-letters"] => commands::dead_letters().await,
+        ["redeliver", id] => commands::redeliver(id).await,
+        ["build-attestation", address] => commands::build_attestation(address, None).await,
+        ["build-attestation", address, nonce] => {
+            commands::build_attestation(address, Some(nonce)).await
+        }
+        ["submit", transaction] => commands::submit(transaction).await,
+        _ => Err(anyhow!(commands::USAGE)),
+    }
+}
+
+async fn run_server() -> Result<()> {
+    let metrics = telemetry::init_metrics().context("couldn't install metrics recorder")?;
+    let config = Config::load().context("invalid configuration")?;
+
+    let mut sas = AttestationService::from_config(&config.solana)?;
+    if sas.signs_offline() {
+        return Err(anyhow!(
+            "signer signs offline, so the server can't issue attestations: \
+            build them with `backend build-attestation` and submit them with `backend submit`"
+        ));
+    }
+    let monitor = balance_monitor_config(&config, &sas)?;
+    sas.init()
+        .await
+        .context("couldn't initialize attestation service")?;
+    let db = db::connect(&config.database.url).await?;
+    if let Some(cap) = config.solana.balance.daily_spend_cap {
+        sas = sas.with_spend_cap(Arc::new(DailySpendCap::new(db.clone(), cap)));
+    }
+    let shared_state = Arc::new(AppState::new(sas, db, &config)?);
+    let recovered = shared_state
+        .jobs
+        .recover()
+        .await
+        .context("couldn't recover verification jobs")?;
+    if recovered > 0 {
+        info!(
+            recovered,
+            "retrying verification jobs interrupted by the last shutdown"
+        );
+    }
+    let released = shared_state
+        .webhooks
+        .recover()
+        .await
+        .context("couldn't recover webhook deliveries")?;
+    if released > 0 {
+        info!(
+            released,
+            "retrying webhooks interrupted by the last shutdown"
+        );
+    }
+    // Drained on shutdown along with requests, the other background tasks are just dropped.
+    let mut workers = jobs::spawn_workers(&shared_state);
+    workers.extend(webhooks::spawn_dispatcher(&shared_state));
+    workers.push(expiry::spawn_scheduler(
+        &shared_state,
+        config.expiry.clone(),
+    ));
+    tokio::spawn({
+        let state = Arc::clone(&shared_state);
+        async move { state.sas.watch_payer_balance(monitor).await }
+    });
+    tokio::spawn({
+        let state = Arc::clone(&shared_state);
+        let commitment = CommitmentConfig {
+            commitment: config.solana.commitment,
+        };
+        async move { indexer::run(&state, commitment).await }
+    });
+    // Streams are off without any allowed, so nothing would read the subscription.
+    if config.limits.max_event_streams > 0 {
+        tokio::spawn({
+            let state = Arc::clone(&shared_state);
+            async move { events::run(&state).await }
+        });
+    }
+    if config.solana.cache.subscribe {
+        tokio::spawn({
+            let state = Arc::clone(&shared_state);
+            async move { state.sas.watch_attestation_changes().await }
+        });
+    }
+    tokio::spawn({
+        let state = Arc::clone(&shared_state);
+        async move {
+            let mut interval = tokio::time::interval(LIMITER_CLEANUP_INTERVAL);
+            loop {
+                interval.tick().await;
+                state.limits.retain_recent();
+                state.webhooks.retain_recent();
+            }
+        }
+    });
+    let max_slot_age = config.health.max_slot_age();
+    let app = api_routes(&shared_state)
+        .into_iter()
+        .fold(Router::new(), |app, (path, route)| app.route(path, route))
+        // Only API routes are tracked, not probes and scrapes below.
+        .route_layer(middleware::from_fn_with_state(
+            Arc::clone(&shared_state),
+            limits::limit_per_ip,
+        ))
+        .route_layer(middleware::from_fn(telemetry::track_requests))
+        .route_layer(middleware::from_fn(telemetry::trace_requests))
+        .route("/healthz", get(health::healthz_handler))
         .route(
-            "/validate",
+            "/readyz",
             get({
                 let state = Arc::clone(&shared_state);
-                move |payload| validate::validate_handler(payload, state)
+                move || health::readyz_handler(state, max_slot_age)
             }),
-        );
+        )
+        .route("/metrics", get(move || async move { metrics.render() }))
+        .route("/openapi.json", get(Json(ApiDoc::openapi())));
+    #[cfg(feature = "swagger-ui")]
+    let app = app.merge(
+        utoipa_swagger_ui::SwaggerUi::new("/swagger-ui")
+            .config(utoipa_swagger_ui::Config::from("/openapi.json")),
+    );
 
-    let listener = TcpListener::bind("0.0.0.0:3000").await.unwrap();
-    axum::serve(listener, app).await.unwrap();
-    Ok(())
+    let listener = TcpListener::bind(config.server.bind)
+        .await
+        .with_context(|| format!("couldn't listen on {}", config.server.bind))?;
+    info!(address = %config.server.bind, "listening");
+    let shutdown = shared_state.shutdown.clone();
+    let drain_timeout = config.server.shutdown_timeout();
+    serve(listener, app, shutdown, workers, drain_timeout).await
 }
@@ -0,0 +1,59 @@
+//! OpenAPI document of the API routes, derived from the handlers and their types.
+//! Served at `/openapi.json`, and browsable at `/swagger-ui` with the `swagger-ui` feature.
+
+use utoipa::OpenApi;
+
+use crate::{events, indexer, jobs, validate, verification};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "SAS backend",
+        description = "Issues Solana Attestation Service attestations to verified wallets, and validates them."
+    ),
+    paths(
+        verification::verification_handler,
+        jobs::enqueue_handler,
+        jobs::job_handler,
+        verification::user_paid_verification_handler,
+        validate::validate_handler,
+        indexer::history_handler,
+        events::events_handler,
+    ),
+    tags(
+        (name = "verification", description = "Issuing attestations"),
+        (name = "validation", description = "Checking attestations"),
+        (name = "attestations", description = "Live attestation updates"),
+    )
+)]
+pub(crate) struct ApiDoc;
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use sas_client::{AttestationService, Cluster};
+    use solana_sdk::signature::Keypair;
+
+    use super::*;
+    use crate::{api_routes, config::Config, db, AppState};
+
+    #[tokio::test]
+    async fn test_every_api_route_is_documented() {
+        let sas = AttestationService::new(
+            Cluster::Localnet,
+            Arc::new(Keypair::new()),
+            Arc::new(Keypair::new()),
+            Arc::new(Keypair::new()),
+        );
+        let db = db::connect("sqlite::memory:").await.unwrap();
+        let state = Arc::new(AppState::new(sas, db, &Config::default()).unwrap());
+
+        let documented = ApiDoc::openapi().paths.paths;
+        let routes = api_routes(&state);
+        for (path, _) in &routes {
+            assert!(documented.contains_key(*path), "{path} isn't documented");
+        }
+        assert_eq!(routes.len(), documented.len());
+    }
+}
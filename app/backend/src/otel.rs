@@ -0,0 +1,94 @@
+use anyhow::Result;
+use axum::http::HeaderMap;
+use opentelemetry::{global, trace::TracerProvider, Context};
+use opentelemetry_http::HeaderExtractor;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{propagation::TraceContextPropagator, trace::SdkTracerProvider, Resource};
+use tracing::Subscriber;
+use tracing_subscriber::{registry::LookupSpan, Layer};
+
+const SERVICE_NAME: &str = "attestation-backend";
+
+/// Exports spans over OTLP/HTTP, to `OTEL_EXPORTER_OTLP_ENDPOINT`
+/// (or `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`). Disabled if neither is set.
+///
+/// The provider has to be shut down before exit, so that buffered spans get flushed.
+pub(crate) fn init_tracer_provider() -> Result<Option<SdkTracerProvider>> {
+    let endpoint = std::env::var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT")
+        .or_else(|_| std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT"));
+    if endpoint.is_err() {
+        return Ok(None);
+    }
+    tracer_provider(None).map(Some)
+}
+
+/// Exports to `traces_endpoint` if given, which overrides the environment's,
+/// e.g. in tests, which can't set the process environment safely.
+fn tracer_provider(traces_endpoint: Option<&str>) -> Result<SdkTracerProvider> {
+    let mut exporter = SpanExporter::builder()
+        .with_http()
+        .with_protocol(opentelemetry_otlp::Protocol::HttpBinary);
+    if let Some(endpoint) = traces_endpoint {
+        exporter = exporter.with_endpoint(endpoint);
+    }
+    let exporter = exporter.build()?;
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(SERVICE_NAME).build())
+        .build();
+
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    Ok(provider)
+}
+
+pub(crate) fn layer<S>(provider: &SdkTracerProvider) -> impl Layer<S>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer(SERVICE_NAME))
+}
+
+/// Remote parent of an incoming request, from its W3C `traceparent`/`tracestate` headers.
+pub(crate) fn extract_context(headers: &HeaderMap) -> Context {
+    global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Bytes, routing::post, Router};
+    use tokio::{net::TcpListener, sync::mpsc};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+
+    /// Exports a span to a stub collector, listening at the OTLP/HTTP traces path.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_span_export() {
+        let (received, mut requests) = mpsc::unbounded_channel();
+        let collector = Router::new().route(
+            "/v1/traces",
+            post(move |body: Bytes| async move {
+                _ = received.send(body);
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}/v1/traces", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, collector).await });
+
+        let provider = tracer_provider(Some(&endpoint)).unwrap();
+        let subscriber = tracing_subscriber::registry().with(layer(&provider));
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("transaction.submit").in_scope(|| {});
+        });
+        // The exporter's HTTP client blocks.
+        tokio::task::spawn_blocking(move || provider.shutdown())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let body = requests.recv().await.unwrap();
+        let contains = |needle: &[u8]| body.windows(needle.len()).any(|window| window == needle);
+        assert!(contains(b"transaction.submit"));
+        assert!(contains(SERVICE_NAME.as_bytes()));
+    }
+}
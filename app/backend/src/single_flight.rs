@@ -0,0 +1,148 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    hash::Hash,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::OnceCell;
+
+/// Coalesces concurrent calls with the same key into one: callers that arrive while a call
+/// is in flight wait for it and get a clone of its result, instead of making their own.
+///
+/// If the caller running the call is cancelled, e.g. its client disconnected,
+/// one of the waiting callers takes over.
+pub(crate) struct SingleFlight<K, T> {
+    calls: Mutex<HashMap<K, Arc<OnceCell<T>>>>,
+}
+
+impl<K: Hash + Eq + Clone, T: Clone> SingleFlight<K, T> {
+    pub fn new() -> Self {
+        Self {
+            calls: Mutex::default(),
+        }
+    }
+
+    pub async fn run<F, Fut>(&self, key: K, call: F) -> T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let cell = Arc::clone(self.calls.lock().unwrap().entry(key.clone()).or_default());
+        let caller = Caller {
+            flight: self,
+            key,
+            cell,
+        };
+        caller.cell.get_or_init(call).await.clone()
+    }
+}
+
+/// Held by every caller of a call, forgetting the call once dropped, whether the caller
+/// finished or was cancelled, so that later callers make a fresh one.
+struct Caller<'a, K: Hash + Eq, T> {
+    flight: &'a SingleFlight<K, T>,
+    key: K,
+    cell: Arc<OnceCell<T>>,
+}
+
+impl<K: Hash + Eq, T> Drop for Caller<'_, K, T> {
+    fn drop(&mut self) {
+        let mut calls = self.flight.calls.lock().unwrap();
+        let current = calls
+            .get(&self.key)
+            .is_some_and(|current| Arc::ptr_eq(current, &self.cell));
+        // A cancelled call is kept while other callers wait on it, one of them takes over.
+        // New callers clone the cell under the same lock, so the count can't change here.
+        let abandoned = Arc::strong_count(&self.cell) == 2;
+        if current && (self.cell.initialized() || abandoned) {
+            calls.remove(&self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use tokio::{sync::Semaphore, task::yield_now};
+
+    use super::*;
+
+    /// Lets spawned callers run until they wait on the call in flight.
+    async fn settle() {
+        for _ in 0..10 {
+            yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_callers_share_one_call() {
+        let flight = Arc::new(SingleFlight::new());
+        let calls = Arc::new(AtomicUsize::new(0));
+        let release = Arc::new(Semaphore::new(0));
+
+        let callers: Vec<_> = (0..5)
+            .map(|_| {
+                let (flight, calls, release) = (flight.clone(), calls.clone(), release.clone());
+                tokio::spawn(async move {
+                    flight
+                        .run("key", || async move {
+                            calls.fetch_add(1, Ordering::SeqCst);
+                            let _permit = release.acquire().await.unwrap();
+                            7
+                        })
+                        .await
+                })
+            })
+            .collect();
+        settle().await;
+        release.add_permits(1);
+        for caller in callers {
+            assert_eq!(caller.await.unwrap(), 7);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Once finished, the next caller makes a fresh call.
+        assert_eq!(flight.run("key", || async { 8 }).await, 8);
+        assert_eq!(flight.run("other", || async { 9 }).await, 9);
+    }
+
+    #[tokio::test]
+    async fn test_waiting_caller_takes_over_from_cancelled_one() {
+        let flight = Arc::new(SingleFlight::new());
+
+        let first = tokio::spawn({
+            let flight = flight.clone();
+            async move { flight.run("key", std::future::pending).await }
+        });
+        settle().await;
+        let second = tokio::spawn({
+            let flight = flight.clone();
+            async move { flight.run("key", || async { 2 }).await }
+        });
+        settle().await;
+        assert!(!second.is_finished());
+
+        first.abort();
+        assert_eq!(second.await.unwrap(), 2);
+        assert!(flight.calls.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_sole_caller_forgets_call() {
+        let flight = Arc::new(SingleFlight::new());
+
+        let caller = tokio::spawn({
+            let flight = flight.clone();
+            async move { flight.run("key", std::future::pending::<u8>).await }
+        });
+        settle().await;
+        assert_eq!(flight.calls.lock().unwrap().len(), 1);
+
+        caller.abort();
+        assert!(caller.await.unwrap_err().is_cancelled());
+        assert!(flight.calls.lock().unwrap().is_empty());
+        assert_eq!(flight.run("key", || async { 3 }).await, 3);
+    }
+}
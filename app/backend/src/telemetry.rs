@@ -0,0 +1,114 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use axum::{extract::MatchedPath, extract::Request, middleware::Next, response::Response};
+use metrics::{describe_counter, describe_histogram, histogram, Unit};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use tracing::{field, info_span, Instrument};
+
+pub(crate) const VERIFICATIONS: &str = "backend_verifications_total";
+pub(crate) const VALIDATIONS: &str = "backend_validations_total";
+pub(crate) const RATE_LIMITED: &str = "backend_rate_limited_total";
+pub(crate) const VALIDATION_EVENTS: &str = "backend_validation_events_total";
+pub(crate) const WEBHOOK_DELIVERIES: &str = "backend_webhook_deliveries_total";
+pub(crate) const EXPIRING_ATTESTATIONS: &str = "backend_expiring_attestations_total";
+const HTTP_REQUEST_DURATION: &str = "backend_http_request_duration_seconds";
+
+const UPKEEP_INTERVAL: Duration = Duration::from_secs(5);
+/// Seconds, from a fast RPC read to a slow confirmation.
+const DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0,
+];
+
+/// Installs the global Prometheus recorder for metrics of both the backend and `sas_client`.
+/// The returned handle renders them for `/metrics`.
+pub(crate) fn init_metrics() -> Result<PrometheusHandle> {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Suffix("seconds".into()), DURATION_BUCKETS)?
+        .install_recorder()?;
+
+    describe_counter!(
+        VERIFICATIONS,
+        "Verification requests by status: created, existing, prepared (user-paid) or failed"
+    );
+    describe_counter!(
+        VALIDATIONS,
+        "Validation requests by verdict, with the AttestError name or failure kind as reason"
+    );
+    describe_counter!(
+        RATE_LIMITED,
+        "Requests rejected with 429, by limit: ip, wallet, transactions or daily_spend"
+    );
+    describe_counter!(
+        VALIDATION_EVENTS,
+        "ValidationResult events indexed from validate program logs, by result"
+    );
+    describe_counter!(
+        WEBHOOK_DELIVERIES,
+        "Webhook delivery attempts by result: delivered, retry or dead"
+    );
+    describe_counter!(
+        EXPIRING_ATTESTATIONS,
+        "Attestations found expiring soon, by action: reminded, renewed or failed"
+    );
+    describe_histogram!(
+        HTTP_REQUEST_DURATION,
+        Unit::Seconds,
+        "HTTP request latency by route"
+    );
+    sas_client::telemetry::describe_metrics();
+
+    // Drains histogram buffers, so that memory doesn't grow between scrapes.
+    tokio::spawn({
+        let handle = handle.clone();
+        async move {
+            let mut interval = tokio::time::interval(UPKEEP_INTERVAL);
+            loop {
+                interval.tick().await;
+                handle.run_upkeep();
+            }
+        }
+    });
+    Ok(handle)
+}
+
+fn matched_route(request: &Request) -> String {
+    request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| "unmatched".to_string(), |path| path.as_str().to_string())
+}
+
+/// Route middleware recording [`HTTP_REQUEST_DURATION`] by route and status.
+pub(crate) async fn track_requests(request: Request, next: Next) -> Response {
+    let route = matched_route(&request);
+    let started = Instant::now();
+    let response = next.run(request).await;
+    histogram!(
+        HTTP_REQUEST_DURATION,
+        "route" => route,
+        "status" => response.status().as_u16().to_string()
+    )
+    .record(started.elapsed());
+    response
+}
+
+/// Route middleware wrapping each request in an `http.request` span.
+/// With the `otel` feature, the span continues the caller's trace from W3C trace context headers.
+pub(crate) async fn trace_requests(request: Request, next: Next) -> Response {
+    let span = info_span!(
+        "http.request",
+        method = %request.method(),
+        route = matched_route(&request),
+        status = field::Empty,
+    );
+    #[cfg(feature = "otel")]
+    {
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+        span.set_parent(crate::otel::extract_context(request.headers()));
+    }
+
+    let response = next.run(request).instrument(span.clone()).await;
+    span.record("status", response.status().as_u16());
+    response
+}
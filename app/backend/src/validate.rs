@@ -1,23 +1,40 @@
 use std::{str::FromStr, sync::Arc};
 
 use anchor_client::{
-    solana_sdk::{pubkey::Pubkey, signature::Signature, sysvar},
-    ClientError,
+    solana_client::client_error::ClientError,
+    solana_sdk::{
+        instruction::InstructionError, pubkey::Pubkey, signature::Signature, sysvar,
+        transaction::TransactionError,
+    },
 };
 
 use anchor_lang::{InstructionData, ToAccountMetas};
-use axum::{extract::Query, Json};
-use sas_client::AttestationService;
+use anyhow::Result;
+use axum::{
+    extract::{rejection::QueryRejection, Query},
+    Json,
+};
+use metrics::counter;
+use sas_client::{AttestationService, TransactionFailed};
 use serde::{Deserialize, Serialize};
 use solana_sdk::instruction::Instruction;
 use test_solana_program::accounts::Validate as ValidateAccounts;
 use test_solana_program::instruction::Validate as ValidateIx;
-use tracing::{field, instrument, warn, Span};
+use test_solana_program::AttestError;
+use tracing::{field, info, instrument, warn, Span};
+use utoipa::{IntoParams, ToSchema};
 
-use crate::AppState;
+use crate::{
+    error::{ApiError, ErrorBody},
+    telemetry::VALIDATIONS,
+    webhooks::WebhookEvent,
+    AppState,
+};
 
 impl AppState {
-    pub(crate) async fn call_validate(&self, user: Pubkey) -> Result<Signature, ClientError> {
+    /// Sent by the SAS client, so that its RPC calls are metered
+    /// and its fees count towards the spend cap.
+    pub(crate) async fn call_validate(&self, user: Pubkey) -> Result<Signature> {
         let accounts = ValidateAccounts {
             attestation: AttestationService::attestation_pda(
                 self.sas.cred_pda,
@@ -35,59 +52,141 @@ impl AppState {
             data: ValidateIx { user_wallet: user }.data(),
         };
 
-        self.validate_program.request().instruction(ix).send().await
+        self.sas.send_as_payer(vec![ix]).await
+    }
+}
+
+impl AppState {
+    async fn emit_validation_failed(&self, address: &str, reason: &str) {
+        let event = WebhookEvent::ValidationFailed {
+            address: address.to_string(),
+            reason: reason.to_string(),
+        };
+        self.webhooks.emit(event).await;
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+/// The [`AttestError`] the validate program rejected the attestation with, if it did,
+/// in preflight or on-chain.
+fn attest_error(err: &anyhow::Error) -> Option<AttestError> {
+    let tx_err = match err.downcast_ref::<ClientError>() {
+        Some(client_err) => client_err.get_transaction_error(),
+        None => err
+            .downcast_ref::<TransactionFailed>()
+            .and_then(|failed| failed.err.clone()),
+    };
+    let Some(TransactionError::InstructionError(_, InstructionError::Custom(code))) = tx_err else {
+        return None;
+    };
+    std::iter::successors(Some(AttestError::WrongOwner), next_attest_error)
+        .find(|attest_err| u32::from(*attest_err) == code)
+}
+
+/// Variant declared after `err`. The match is exhaustive, so that a variant added
+/// to the program fails to compile until it's chained here too.
+fn next_attest_error(err: &AttestError) -> Option<AttestError> {
+    match err {
+        AttestError::WrongOwner => Some(AttestError::InvalidAttestationPda),
+        AttestError::InvalidAttestationPda => Some(AttestError::DecodeFailed),
+        AttestError::DecodeFailed => Some(AttestError::HeaderMismatch),
+        AttestError::HeaderMismatch => Some(AttestError::Expired),
+        AttestError::Expired => Some(AttestError::SchemaMismatch),
+        AttestError::SchemaMismatch => None,
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub(crate) struct ValidatePayload {
+    /// Wallet whose attestation is validated.
     address: String,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Clone, ToSchema)]
 pub(crate) struct ValidateResponse {
     address: String,
     valid: bool,
 }
 
+/// Validates the attestation of `address` with the validate program. Never served from cache,
+/// as attestations may be revoked by other processes, e.g. CLI commands.
+#[utoipa::path(
+    get,
+    path = "/validate",
+    tag = "validation",
+    params(ValidatePayload),
+    responses(
+        (status = 200, body = ValidateResponse),
+        (status = 400, description = "Malformed request", body = ErrorBody),
+        (status = 404, description = "No attestation for the address", body = ErrorBody),
+        (status = 429, description = "Rate limited", body = ErrorBody),
+        (status = 502, description = "RPC node or the chain rejected the transaction", body = ErrorBody),
+        (status = 503, description = "RPC node unreachable or payer out of funds", body = ErrorBody),
+    )
+)]
 #[instrument(
-    skip(state),
-    fields(pubkey = %payload.address, success = field::Empty))
+    skip_all,
+    fields(pubkey = field::Empty, success = field::Empty, signature = field::Empty))
 ]
 pub(crate) async fn validate_handler(
-    Query(payload): Query<ValidatePayload>,
+    payload: Result<Query<ValidatePayload>, QueryRejection>,
     state: Arc<AppState>,
-) -> Json<ValidateResponse> {
+) -> Result<Json<ValidateResponse>, ApiError> {
+    let Query(payload) = payload?;
     let span = Span::current();
+    span.record("pubkey", &payload.address);
 
-    let mut response = ValidateResponse {
-        address: payload.address,
-        valid: true,
-    };
+    let pubkey = Pubkey::from_str(&payload.address).map_err(|err| {
+        span.record("success", false);
+        counter!(VALIDATIONS, "verdict" => "invalid", "reason" => "invalid_address").increment(1);
+        warn!(%payload.address, %err, "invalid pubkey");
+        ApiError::invalid_address(&payload.address)
+    })?;
+    state.limits.check_wallet(&pubkey)?;
 
-    let pubkey = match Pubkey::from_str(&response.address) {
-        Ok(pubkey) => pubkey,
-        Err(err) => {
-            span.record("success", false);
-            warn!(%response.address, %err, "invalid pubkey");
-            response.valid = false;
-            return Json(response);
-        }
-    };
+    let _permit = state.limits.transaction_permit()?;
+    state.sas.ensure_funds().inspect_err(|err| {
+        span.record("success", false);
+        warn!(%err, "payer can't afford validation");
+    })?;
 
     match state.call_validate(pubkey).await {
-        Ok(_sig) => {
+        Ok(sig) => {
+            span.record("signature", field::display(sig));
             // FIX: We don't actually know if the program returned true or false.
             // Retrieving that info is clunky. I guess, for now we can assume that attestations are
             // always created with {true, true}.
             span.record("success", true);
-            Json(response)
+            counter!(VALIDATIONS, "verdict" => "valid", "reason" => "none").increment(1);
+            Ok(Json(ValidateResponse {
+                address: payload.address,
+                valid: true,
+            }))
         }
         Err(err) => {
             span.record("success", false);
-            warn!(%err, "couldn't validate attestation");
-            response.valid = false;
-            Json(response)
+            let Some(reason) = attest_error(&err) else {
+                counter!(VALIDATIONS, "verdict" => "error", "reason" => "rpc").increment(1);
+                warn!(%err, "couldn't validate attestation");
+                return Err(err.into());
+            };
+            counter!(VALIDATIONS, "verdict" => "invalid", "reason" => format!("{reason:?}"))
+                .increment(1);
+            info!(?reason, "attestation is invalid");
+            state
+                .emit_validation_failed(&payload.address, &format!("{reason:?}"))
+                .await;
+            // The attestation PDA isn't owned by SAS when there's no account at all.
+            if matches!(reason, AttestError::WrongOwner) {
+                return Err(ApiError::NotFound(format!(
+                    "no attestation for {}",
+                    payload.address
+                )));
+            }
+            Ok(Json(ValidateResponse {
+                address: payload.address,
+                valid: false,
+            }))
         }
     }
 }
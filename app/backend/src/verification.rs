@@ -1,25 +1,47 @@
 use std::{str::FromStr, sync::Arc};
 
-use axum::Json;
+use axum::{extract::rejection::JsonRejection, http::StatusCode, Extension, Json};
+use metrics::counter;
+use sas_client::{
+    transaction, AttestationExists, AttestationPayload, AttestationService, TransactionFailed,
+};
 use serde::{Deserialize, Serialize};
 use solana_sdk::pubkey::Pubkey;
-use tracing::{debug_span, field, info, instrument, warn, Instrument};
+use tracing::{debug_span, error, field, info, instrument, warn, Instrument, Span};
+use utoipa::ToSchema;
 
-use crate::AppState;
+use crate::{
+    error::{ApiError, ErrorBody, ErrorDetails},
+    ledger::Requester,
+    telemetry::VERIFICATIONS,
+    webhooks::WebhookEvent,
+    AppState,
+};
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, ToSchema)]
 pub(crate) struct VerificationPayload {
-    address: String,
+    /// Wallet to attest.
+    #[schema(example = "5HnSzDfPiTEb7oxPwAfGrBoExqYb2hoXtwDjN97sXu9h")]
+    pub address: String,
 }
 
-#[derive(Debug, Serialize, Clone, Copy)]
-pub(crate) struct VerificationResponse {
+/// Claims an attestation holds.
+#[derive(Debug, Serialize, Clone, Copy, ToSchema)]
+pub(crate) struct Claims {
     age: bool,
     country: bool,
 }
 
-impl From<VerificationResponse> for sas_client::AttestationPayload {
-    fn from(value: VerificationResponse) -> Self {
+impl Claims {
+    /// Every user passes verification for now.
+    pub const VERIFIED: Self = Self {
+        age: true,
+        country: true,
+    };
+}
+
+impl From<Claims> for AttestationPayload {
+    fn from(value: Claims) -> Self {
         Self {
             age: value.age,
             country: value.country,
@@ -27,66 +49,264 @@ impl From<VerificationResponse> for sas_client::AttestationPayload {
     }
 }
 
-#[instrument(
-    skip(state),
-    fields(pubkey = %payload.address))
-]
+impl From<AttestationPayload> for Claims {
+    fn from(value: AttestationPayload) -> Self {
+        Self {
+            age: value.age,
+            country: value.country,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Copy, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum VerificationStatus {
+    /// Attestation was issued by this request.
+    Created,
+    /// Attestation was already on-chain, and was left as is.
+    Existing,
+    /// Attestation transaction was sent, but failed or wasn't confirmed in time.
+    Failed,
+}
+
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub(crate) struct VerificationResponse {
+    status: VerificationStatus,
+    /// Attestation PDA.
+    attestation: String,
+    /// Claims the attestation holds on-chain, `null` if it failed.
+    payload: Option<Claims>,
+    /// Transaction creating the attestation, unless it already existed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signature: Option<String>,
+    /// Why the transaction failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ErrorDetails>,
+}
+
+/// Parses `address`, counting a failed verification if it isn't a valid pubkey.
+pub(crate) fn parse_address(address: &str) -> Result<Pubkey, ApiError> {
+    Pubkey::from_str(address).map_err(|err| {
+        counter!(VERIFICATIONS, "status" => "failed").increment(1);
+        warn!(pubkey = %address, %err, "invalid pubkey");
+        ApiError::invalid_address(address)
+    })
+}
+
+/// Responds with the attestation as it is on-chain, issuing it first if there's none.
+/// A sent transaction that failed is a 502 with `failed` status and the transaction signature.
+///
+/// Concurrent requests for the same wallet share one verification and its response.
+#[utoipa::path(
+    post,
+    path = "/verification",
+    tag = "verification",
+    request_body = VerificationPayload,
+    responses(
+        (status = 200, description = "Attestation already existed", body = VerificationResponse),
+        (status = 201, description = "Attestation was issued", body = VerificationResponse),
+        (status = 400, description = "Malformed request", body = ErrorBody),
+        (status = 429, description = "Rate limited", body = ErrorBody),
+        (status = 502, description = "Transaction failed, with `failed` status", body = VerificationResponse),
+        (status = 503, description = "RPC node unreachable or payer out of funds", body = ErrorBody),
+    )
+)]
+#[instrument(skip_all, fields(pubkey = field::Empty))]
 pub(crate) async fn verification_handler(
-    Json(payload): Json<VerificationPayload>,
+    Extension(requester): Extension<Requester>,
+    payload: Result<Json<VerificationPayload>, JsonRejection>,
     state: Arc<AppState>,
-) -> Json<VerificationResponse> {
-    let success_response = Json(VerificationResponse {
-        age: true,
-        country: true,
-    });
+) -> Result<(StatusCode, Json<VerificationResponse>), ApiError> {
+    let Json(payload) = payload?;
+    Span::current().record("pubkey", &payload.address);
+    let user_pubkey = parse_address(&payload.address)?;
+    state.limits.check_wallet(&user_pubkey)?;
 
-    let span = debug_span!("attestation.fetch",
-        pubkey = %payload.address,
+    let (status, response) = state
+        .verifications
+        .run(user_pubkey, || verify(&state, user_pubkey, &requester))
+        .await?;
+    Ok((status, Json(response)))
+}
+
+/// Shared result of a verification, see [`crate::single_flight::SingleFlight`].
+pub(crate) type Verification = Result<(StatusCode, VerificationResponse), ApiError>;
+
+async fn verify(state: &AppState, user_pubkey: Pubkey, requester: &Requester) -> Verification {
+    let attestation =
+        AttestationService::attestation_pda(state.sas.cred_pda, state.sas.schema_pda, user_pubkey)
+            .to_string();
+
+    if let Some(payload) = fetch_attestation(state, user_pubkey).await? {
+        counter!(VERIFICATIONS, "status" => "existing").increment(1);
+        info!("attestation exists, skipping");
+        return Ok(existing(attestation, payload));
+    }
+
+    let _permit = state.limits.transaction_permit()?;
+    let span = debug_span!("attestation.create",
+        pubkey = %user_pubkey,
         success = field::Empty
     );
-    match Pubkey::from_str(&payload.address) {
-        Ok(user_pubkey) => match state
-            .sas
-            .fetch_attestation(user_pubkey)
-            .instrument(span.clone())
-            .await
-        {
-            Ok(None) => {
-                span.record("success", true);
-                let span = debug_span!("attestation.create",
-                    pubkey = %payload.address,
-                    success = field::Empty
-                );
-                if let Err(err) = state
-                    .sas
-                    .create_attestation(user_pubkey, success_response.0.into())
-                    .instrument(span.clone())
-                    .await
-                {
-                    span.record("success", false);
-                    warn!(%err, "couldn't attest user")
-                } else {
-                    span.record("success", true);
-                }
-            }
-            Ok(Some(_)) => {
-                span.record("success", true);
-                info!("attestation exists, skipping");
+    let created = state
+        .sas
+        .create_attestation(user_pubkey, Claims::VERIFIED.into())
+        .instrument(span.clone())
+        .await;
+    span.record("success", created.is_ok());
+    match created {
+        Ok(issued) => {
+            counter!(VERIFICATIONS, "status" => "created").increment(1);
+            let recorded = state
+                .ledger
+                .record_issued(
+                    &user_pubkey,
+                    &Claims::VERIFIED.into(),
+                    &issued,
+                    requester,
+                    None,
+                )
+                .await;
+            if let Err(err) = recorded {
+                error!(%err, "couldn't record attestation in the ledger");
             }
-            Err(err) => {
-                span.record("success", false);
-                warn!(%err, "couldn't fetch attestation");
-            }
-        },
+            state
+                .webhooks
+                .emit(WebhookEvent::created(&user_pubkey, &issued))
+                .await;
+            Ok((
+                StatusCode::CREATED,
+                VerificationResponse {
+                    status: VerificationStatus::Created,
+                    attestation,
+                    payload: Some(Claims::VERIFIED),
+                    signature: Some(issued.signature.to_string()),
+                    error: None,
+                },
+            ))
+        }
+        // Someone else, e.g. a verification job, created it in the meantime.
+        Err(err) if err.is::<AttestationExists>() => {
+            counter!(VERIFICATIONS, "status" => "existing").increment(1);
+            info!("attestation was created concurrently");
+            let payload = fetch_attestation(state, user_pubkey)
+                .await?
+                .ok_or_else(|| ApiError::Upstream(format!("attestation {attestation} vanished")))?;
+            Ok(existing(attestation, payload))
+        }
         Err(err) => {
-            span.record("success", false);
-            warn!(pubkey = %payload.address, %err, "invalid pubkey");
-            return Json(VerificationResponse {
-                age: false,
-                country: false,
-            });
+            counter!(VERIFICATIONS, "status" => "failed").increment(1);
+            warn!(%err, "couldn't attest user");
+            let Some(failed) = err.downcast_ref::<TransactionFailed>() else {
+                return Err(err.into());
+            };
+            Ok((
+                StatusCode::BAD_GATEWAY,
+                VerificationResponse {
+                    status: VerificationStatus::Failed,
+                    attestation,
+                    payload: None,
+                    signature: Some(failed.signature.to_string()),
+                    error: Some(ErrorDetails {
+                        code: "transaction_failed",
+                        message: failed.to_string(),
+                        retry_after_secs: None,
+                    }),
+                },
+            ))
         }
     }
+}
 
-    success_response
+async fn fetch_attestation(
+    state: &AppState,
+    user_pubkey: Pubkey,
+) -> Result<Option<AttestationPayload>, ApiError> {
+    let span = debug_span!("attestation.fetch",
+        pubkey = %user_pubkey,
+        success = field::Empty
+    );
+    let fetched = state
+        .sas
+        .fetch_attestation(user_pubkey)
+        .instrument(span.clone())
+        .await;
+    span.record("success", fetched.is_ok());
+    fetched.map_err(|err| {
+        counter!(VERIFICATIONS, "status" => "failed").increment(1);
+        warn!(%err, "couldn't fetch attestation");
+        err.into()
+    })
+}
+
+fn existing(
+    attestation: String,
+    payload: AttestationPayload,
+) -> (StatusCode, VerificationResponse) {
+    (
+        StatusCode::OK,
+        VerificationResponse {
+            status: VerificationStatus::Existing,
+            attestation,
+            payload: Some(payload.into()),
+            signature: None,
+            error: None,
+        },
+    )
+}
+
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub(crate) struct UserPaidVerificationResponse {
+    /// Attestation PDA the transaction creates.
+    attestation: String,
+    /// Base64-encoded transaction, signed by the attestation signer.
+    /// The user signs it as fee payer and submits it themselves.
+    transaction: String,
+}
+
+/// Same as [`verification_handler`], but the attestation is created by the user's wallet,
+/// which pays for the transaction fee and the account rent.
+#[utoipa::path(
+    post,
+    path = "/verification/transaction",
+    tag = "verification",
+    request_body = VerificationPayload,
+    responses(
+        (status = 200, description = "Transaction for the user to sign and submit", body = UserPaidVerificationResponse),
+        (status = 400, description = "Malformed request", body = ErrorBody),
+        (status = 429, description = "Rate limited", body = ErrorBody),
+        (status = 503, description = "RPC node unreachable", body = ErrorBody),
+    )
+)]
+#[instrument(skip_all, fields(pubkey = field::Empty, success = field::Empty))]
+pub(crate) async fn user_paid_verification_handler(
+    payload: Result<Json<VerificationPayload>, JsonRejection>,
+    state: Arc<AppState>,
+) -> Result<Json<UserPaidVerificationResponse>, ApiError> {
+    let Json(payload) = payload?;
+    let span = Span::current();
+    span.record("pubkey", &payload.address);
+    let user_pubkey = parse_address(&payload.address)?;
+    state.limits.check_wallet(&user_pubkey)?;
+
+    let built = state
+        .sas
+        .build_user_paid_attestation(user_pubkey, Claims::VERIFIED.into())
+        .await
+        .and_then(|(pda, tx)| Ok((pda, transaction::encode_transaction(&tx)?)));
+    span.record("success", built.is_ok());
+    match built {
+        Ok((pda, tx)) => {
+            counter!(VERIFICATIONS, "status" => "prepared").increment(1);
+            Ok(Json(UserPaidVerificationResponse {
+                attestation: pda.to_string(),
+                transaction: tx,
+            }))
+        }
+        Err(err) => {
+            counter!(VERIFICATIONS, "status" => "failed").increment(1);
+            warn!(%err, "couldn't build user-paid attestation");
+            Err(err.into())
+        }
+    }
 }
@@ -0,0 +1,555 @@
+//! Webhooks: attestation lifecycle events POSTed to configured URLs, signed with HMAC-SHA256.
+//! Deliveries are stored in SQLite and retried with backoff. Those that run out of attempts
+//! stay there as the dead-letter log, see `backend dead-letters`.
+
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{Context, Result};
+use governor::{DefaultKeyedRateLimiter, Quota};
+use hmac::{Hmac, Mac};
+use metrics::counter;
+use reqwest::{header, Client};
+use sas_client::{IssuedAttestation, RevokedAttestation};
+use serde::Serialize;
+use sha2::Sha256;
+use solana_sdk::pubkey::Pubkey;
+use sqlx::SqlitePool;
+use tokio::{sync::Notify, task::JoinHandle};
+use tracing::{debug, error, info, instrument, warn};
+use uuid::Uuid;
+
+use crate::{config::WebhooksConfig, db::unix_now, telemetry::WEBHOOK_DELIVERIES, AppState};
+
+/// How often an idle dispatcher looks for deliveries whose retry is due.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(3600);
+/// How often delivered events past their retention are deleted.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Event sent to every webhook, as `{"id", "type", "created_at", "data"}`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "data")]
+pub(crate) enum WebhookEvent {
+    #[serde(rename = "attestation.created")]
+    AttestationCreated {
+        address: String,
+        attestation: String,
+        signature: String,
+        slot: u64,
+        expiry: i64,
+    },
+    #[serde(rename = "attestation.revoked")]
+    AttestationRevoked {
+        address: String,
+        attestation: String,
+        signature: String,
+        slot: u64,
+        reason: Option<String>,
+    },
+    /// Expires within `expiry.window_secs`, and isn't renewed automatically.
+    #[serde(rename = "attestation.expiring")]
+    AttestationExpiring {
+        address: String,
+        attestation: String,
+        expiry: i64,
+    },
+    /// Re-created with a fresh expiry, by `expiry.auto_renew`.
+    #[serde(rename = "attestation.renewed")]
+    AttestationRenewed {
+        address: String,
+        attestation: String,
+        signature: String,
+        slot: u64,
+        expiry: i64,
+        previous_expiry: i64,
+    },
+    /// `/validate` found the attestation invalid or missing.
+    #[serde(rename = "validation.failed")]
+    ValidationFailed {
+        address: String,
+        /// `AttestError` name.
+        reason: String,
+    },
+}
+
+impl WebhookEvent {
+    pub fn created(user: &Pubkey, issued: &IssuedAttestation) -> Self {
+        Self::AttestationCreated {
+            address: user.to_string(),
+            attestation: issued.pda.to_string(),
+            signature: issued.signature.to_string(),
+            slot: issued.slot,
+            expiry: issued.expiry,
+        }
+    }
+
+    pub fn revoked(user: &Pubkey, revoked: &RevokedAttestation, reason: Option<&str>) -> Self {
+        Self::AttestationRevoked {
+            address: user.to_string(),
+            attestation: revoked.pda.to_string(),
+            signature: revoked.signature.to_string(),
+            slot: revoked.slot,
+            reason: reason.map(str::to_string),
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::AttestationCreated { .. } => "attestation.created",
+            Self::AttestationRevoked { .. } => "attestation.revoked",
+            Self::AttestationExpiring { .. } => "attestation.expiring",
+            Self::AttestationRenewed { .. } => "attestation.renewed",
+            Self::ValidationFailed { .. } => "validation.failed",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    id: &'a str,
+    #[serde(flatten)]
+    event: &'a WebhookEvent,
+    created_at: i64,
+}
+
+/// A delivery in the dead-letter log.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub(crate) struct DeadLetter {
+    pub id: i64,
+    pub event_id: String,
+    pub event_type: String,
+    pub url: String,
+    pub attempts: u32,
+    pub error: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// A delivery claimed by the dispatcher.
+#[derive(Debug, sqlx::FromRow)]
+struct Claimed {
+    id: i64,
+    event_id: String,
+    event_type: String,
+    url: String,
+    payload: String,
+    attempts: u32,
+}
+
+pub(crate) struct Webhooks {
+    db: SqlitePool,
+    client: Client,
+    urls: Vec<String>,
+    secret: Vec<u8>,
+    /// Wakes the dispatcher when an event is emitted.
+    emitted: Notify,
+    max_attempts: u32,
+    retry_delay: Duration,
+    /// Lets one `validation.failed` per address through within the configured window.
+    validation_failures: Option<DefaultKeyedRateLimiter<String>>,
+    delivered_retention: Duration,
+}
+
+impl Webhooks {
+    pub fn new(db: SqlitePool, config: &WebhooksConfig) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(config.timeout())
+            .build()
+            .context("couldn't build webhook client")?;
+        Ok(Self {
+            db,
+            client,
+            urls: config.urls.clone(),
+            secret: config.secret.clone().unwrap_or_default().into_bytes(),
+            emitted: Notify::new(),
+            max_attempts: config.max_attempts,
+            retry_delay: config.retry_delay(),
+            validation_failures: Quota::with_period(config.validation_failed_window())
+                .map(DefaultKeyedRateLimiter::keyed),
+            delivered_retention: config.delivered_retention(),
+        })
+    }
+
+    /// Queues `event` for every URL. Failures are logged, as they shouldn't fail
+    /// whatever the event is about.
+    pub async fn emit(&self, event: WebhookEvent) {
+        if self.urls.is_empty() {
+            return;
+        }
+        if let (WebhookEvent::ValidationFailed { address, .. }, Some(limiter)) =
+            (&event, &self.validation_failures)
+        {
+            if limiter.check_key(address).is_err() {
+                debug!(%address, "validation.failed already sent recently");
+                return;
+            }
+        }
+        if let Err(err) = self.enqueue(&event).await {
+            error!(%err, event = event.kind(), "couldn't queue webhook event");
+        }
+    }
+
+    async fn enqueue(&self, event: &WebhookEvent) -> Result<()> {
+        let id = Uuid::new_v4().to_string();
+        let now = unix_now();
+        let payload = serde_json::to_string(&Envelope {
+            id: &id,
+            event,
+            created_at: now,
+        })?;
+        let mut tx = self.db.begin().await?;
+        for url in &self.urls {
+            sqlx::query(
+                "INSERT INTO webhook_deliveries
+                    (event_id, event_type, url, payload, next_attempt_at, created_at, updated_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?5, ?5)",
+            )
+            .bind(&id)
+            .bind(event.kind())
+            .bind(url)
+            .bind(&payload)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        self.emitted.notify_one();
+        Ok(())
+    }
+
+    /// Forgets addresses whose `validation.failed` window is over.
+    pub fn retain_recent(&self) {
+        if let Some(limiter) = &self.validation_failures {
+            limiter.retain_recent();
+        }
+    }
+
+    /// Deletes deliveries that were delivered longer than the retention ago.
+    async fn prune_delivered(&self) -> Result<u64> {
+        let before = unix_now() - self.delivered_retention.as_secs() as i64;
+        let pruned = sqlx::query(
+            "DELETE FROM webhook_deliveries WHERE status = 'delivered' AND updated_at < ?",
+        )
+        .bind(before)
+        .execute(&self.db)
+        .await?
+        .rows_affected();
+        Ok(pruned)
+    }
+
+    /// Releases deliveries claimed by a previous run, which was stopped mid-request.
+    pub async fn recover(&self) -> Result<u64> {
+        let released = sqlx::query("UPDATE webhook_deliveries SET claimed = 0 WHERE claimed = 1")
+            .execute(&self.db)
+            .await?
+            .rows_affected();
+        Ok(released)
+    }
+
+    pub async fn dead_letters(&self) -> Result<Vec<DeadLetter>> {
+        let dead = sqlx::query_as(
+            "SELECT id, event_id, event_type, url, attempts, error, created_at, updated_at
+            FROM webhook_deliveries WHERE status = 'dead' ORDER BY id",
+        )
+        .fetch_all(&self.db)
+        .await?;
+        Ok(dead)
+    }
+
+    /// Moves a dead delivery back to the queue, with its attempts reset.
+    /// Returns whether there was one with `id`.
+    pub async fn redeliver(&self, id: i64) -> Result<bool> {
+        let requeued = sqlx::query(
+            "UPDATE webhook_deliveries
+            SET status = 'pending', attempts = 0, next_attempt_at = ?2, updated_at = ?2
+            WHERE id = ?1 AND status = 'dead'",
+        )
+        .bind(id)
+        .bind(unix_now())
+        .execute(&self.db)
+        .await?
+        .rows_affected();
+        Ok(requeued > 0)
+    }
+
+    /// Oldest due delivery, counting an attempt.
+    async fn claim(&self) -> Result<Option<Claimed>> {
+        let claimed = sqlx::query_as(
+            "UPDATE webhook_deliveries
+            SET claimed = 1, attempts = attempts + 1, updated_at = ?1
+            WHERE id = (
+                SELECT id FROM webhook_deliveries
+                WHERE status = 'pending' AND claimed = 0 AND next_attempt_at <= ?1
+                ORDER BY next_attempt_at
+                LIMIT 1
+            )
+            RETURNING id, event_id, event_type, url, payload, attempts",
+        )
+        .bind(unix_now())
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(claimed)
+    }
+
+    async fn delivered(&self, id: i64) -> Result<()> {
+        sqlx::query(
+            "UPDATE webhook_deliveries
+            SET status = 'delivered', claimed = 0, error = NULL, updated_at = ?2
+            WHERE id = ?1",
+        )
+        .bind(id)
+        .bind(unix_now())
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Schedules a retry with exponential backoff, or moves the delivery to the dead-letter log
+    /// after the last attempt. Returns whether it's dead.
+    async fn retry_or_kill(&self, delivery: &Claimed, err: &anyhow::Error) -> Result<bool> {
+        let dead = delivery.attempts >= self.max_attempts;
+        let backoff = self
+            .retry_delay
+            .saturating_mul(1 << delivery.attempts.saturating_sub(1).min(16))
+            .min(MAX_RETRY_DELAY);
+        let now = unix_now();
+        sqlx::query(
+            "UPDATE webhook_deliveries
+            SET status = ?2, claimed = 0, error = ?3, next_attempt_at = ?4, updated_at = ?5
+            WHERE id = ?1",
+        )
+        .bind(delivery.id)
+        .bind(if dead { "dead" } else { "pending" })
+        .bind(format!("{err:#}"))
+        .bind(now + backoff.as_secs() as i64)
+        .bind(now)
+        .execute(&self.db)
+        .await?;
+        Ok(dead)
+    }
+
+    async fn post(&self, delivery: &Claimed) -> Result<()> {
+        let timestamp = unix_now().to_string();
+        let signature = sign(&self.secret, &timestamp, &delivery.payload);
+        self.client
+            .post(&delivery.url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header("X-Webhook-Id", &delivery.event_id)
+            .header("X-Webhook-Timestamp", &timestamp)
+            .header("X-Webhook-Signature", format!("sha256={signature}"))
+            .body(delivery.payload.clone())
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Hex HMAC-SHA256 of `{timestamp}.{payload}`, so that receivers can reject replays.
+fn sign(secret: &[u8], timestamp: &str, payload: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC takes keys of any size");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(payload.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Delivers queued events one at a time, and prunes delivered ones, as long as the process runs.
+/// Does nothing if no URLs are configured, leaving anything queued for a later run.
+pub(crate) fn spawn_dispatcher(state: &Arc<AppState>) -> Vec<JoinHandle<()>> {
+    if state.webhooks.urls.is_empty() {
+        return Vec::new();
+    }
+    let pruner = tokio::spawn({
+        let state = Arc::clone(state);
+        async move {
+            let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = state.shutting_down() => return,
+                }
+                match state.webhooks.prune_delivered().await {
+                    Ok(0) => {}
+                    Ok(pruned) => info!(pruned, "pruned delivered webhooks"),
+                    Err(err) => warn!(%err, "couldn't prune delivered webhooks"),
+                }
+            }
+        }
+    });
+    let state = Arc::clone(state);
+    let dispatcher = tokio::spawn(async move {
+        while !state.is_shutting_down() {
+            match state.webhooks.claim().await {
+                Ok(Some(delivery)) => dispatch(&state.webhooks, delivery).await,
+                Ok(None) => tokio::select! {
+                    _ = tokio::time::timeout(POLL_INTERVAL, state.webhooks.emitted.notified()) => {}
+                    _ = state.shutting_down() => {}
+                },
+                Err(err) => {
+                    error!(%err, "couldn't claim webhook delivery");
+                    tokio::select! {
+                        _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                        _ = state.shutting_down() => {}
+                    }
+                }
+            }
+        }
+    });
+    vec![pruner, dispatcher]
+}
+
+#[instrument(name = "webhook.delivery", skip_all, fields(delivery = delivery.id, event = %delivery.event_type, url = %delivery.url, attempt = delivery.attempts))]
+async fn dispatch(webhooks: &Webhooks, delivery: Claimed) {
+    let stored = match webhooks.post(&delivery).await {
+        Ok(()) => {
+            counter!(WEBHOOK_DELIVERIES, "result" => "delivered").increment(1);
+            info!("webhook delivered");
+            webhooks.delivered(delivery.id).await
+        }
+        Err(err) => {
+            warn!(%err, "webhook delivery failed");
+            webhooks.retry_or_kill(&delivery, &err).await.map(|dead| {
+                let result = if dead { "dead" } else { "retry" };
+                counter!(WEBHOOK_DELIVERIES, "result" => result).increment(1);
+                if dead {
+                    error!("webhook delivery moved to the dead-letter log");
+                }
+            })
+        }
+    };
+    if let Err(err) = stored {
+        error!(%err, "couldn't update webhook delivery");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    async fn open(config: WebhooksConfig) -> Webhooks {
+        let db = crate::db::connect("sqlite::memory:").await.unwrap();
+        let config = WebhooksConfig {
+            urls: vec!["http://127.0.0.1:9/hook".to_string()],
+            secret: Some("whsec_test".to_string()),
+            ..config
+        };
+        Webhooks::new(db, &config).unwrap()
+    }
+
+    async fn queued(webhooks: &Webhooks) -> Vec<String> {
+        sqlx::query_scalar("SELECT event_type FROM webhook_deliveries ORDER BY id")
+            .fetch_all(&webhooks.db)
+            .await
+            .unwrap()
+    }
+
+    fn failed(address: &str) -> WebhookEvent {
+        WebhookEvent::ValidationFailed {
+            address: address.to_string(),
+            reason: "Expired".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_sign() {
+        assert_eq!(
+            sign(b"whsec_test", "1700000000", r#"{"id":"evt"}"#),
+            "a94cea056df1fbb92eadafcf2c5cd541dbe0c6ef736e4748202dd53f86694a3e"
+        );
+    }
+
+    #[test]
+    fn test_envelope() {
+        let event = failed("5HnSzDfPiTEb7oxPwAfGrBoExqYb2hoXtwDjN97sXu9h");
+        let envelope = serde_json::to_value(Envelope {
+            id: "961b151f-ead8-427a-a8fa-2604f1c31282",
+            event: &event,
+            created_at: 1761955200,
+        })
+        .unwrap();
+        assert_eq!(
+            envelope,
+            json!({
+                "id": "961b151f-ead8-427a-a8fa-2604f1c31282",
+                "type": "validation.failed",
+                "data": {
+                    "address": "5HnSzDfPiTEb7oxPwAfGrBoExqYb2hoXtwDjN97sXu9h",
+                    "reason": "Expired",
+                },
+                "created_at": 1761955200,
+            })
+        );
+
+        let expiring = WebhookEvent::AttestationExpiring {
+            address: String::new(),
+            attestation: String::new(),
+            expiry: 0,
+        };
+        for event in [event, expiring] {
+            let tagged = serde_json::to_value(&event).unwrap();
+            assert_eq!(tagged["type"], event.kind());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validation_failed_once_per_window() {
+        let webhooks = open(WebhooksConfig::default()).await;
+        webhooks.emit(failed("a")).await;
+        webhooks.emit(failed("a")).await;
+        webhooks.emit(failed("b")).await;
+        let expiring = WebhookEvent::AttestationExpiring {
+            address: "a".to_string(),
+            attestation: String::new(),
+            expiry: 0,
+        };
+        webhooks.emit(expiring.clone()).await;
+        webhooks.emit(expiring).await;
+        assert_eq!(
+            queued(&webhooks).await,
+            [
+                "validation.failed",
+                "validation.failed",
+                "attestation.expiring",
+                "attestation.expiring"
+            ]
+        );
+
+        let unlimited = open(WebhooksConfig {
+            validation_failed_window_secs: 0,
+            ..WebhooksConfig::default()
+        })
+        .await;
+        unlimited.emit(failed("a")).await;
+        unlimited.emit(failed("a")).await;
+        assert_eq!(queued(&unlimited).await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_prune_delivered() {
+        let webhooks = open(WebhooksConfig {
+            delivered_retention_secs: 60,
+            ..WebhooksConfig::default()
+        })
+        .await;
+        for address in ["a", "b", "c"] {
+            webhooks.emit(failed(address)).await;
+        }
+        let first = webhooks.claim().await.unwrap().unwrap();
+        webhooks.delivered(first.id).await.unwrap();
+        let second = webhooks.claim().await.unwrap().unwrap();
+        webhooks.delivered(second.id).await.unwrap();
+        // Delivered before the retention, pending, and delivered within it.
+        sqlx::query("UPDATE webhook_deliveries SET updated_at = updated_at - 61 WHERE id = ?")
+            .bind(first.id)
+            .execute(&webhooks.db)
+            .await
+            .unwrap();
+
+        assert_eq!(webhooks.prune_delivered().await.unwrap(), 1);
+        assert_eq!(queued(&webhooks).await.len(), 2);
+        assert_eq!(webhooks.prune_delivered().await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,335 @@
+use std::{
+    fmt,
+    sync::{atomic::Ordering, Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use metrics::gauge;
+use solana_attestation_service_client::programs::SOLANA_ATTESTATION_SERVICE_ID;
+use solana_sdk::{
+    native_token::LAMPORTS_PER_SOL, rent::Rent, signature::Signature,
+    transaction::VersionedTransaction,
+};
+use solana_system_interface::instruction::transfer;
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, info, warn};
+
+use crate::{
+    confirm, telemetry, transaction, AttestationPayload, AttestationService, TransactionSigner,
+    COMPUTE_UNIT_LIMIT, COMPUTE_UNIT_PRICE,
+};
+
+/// Base fee of every signature a transaction carries.
+pub const LAMPORTS_PER_SIGNATURE: u64 = 5000;
+/// First byte of `CreateAttestation` instruction data.
+const CREATE_ATTESTATION_DISCRIMINATOR: u8 = 6;
+/// Attestation account layout: discriminator, nonce, credential, schema, payload with its
+/// length, signer, expiry and token account.
+const ATTESTATION_ACCOUNT_SIZE: usize =
+    1 + 32 + 32 + 32 + 4 + AttestationPayload::layout().len() + 32 + 8 + 32;
+
+/// Where to get lamports from when payer runs low.
+#[derive(Clone, Default)]
+pub enum TopUp {
+    #[default]
+    Disabled,
+    /// Refused outside of test clusters, see [`crate::Cluster::is_test_cluster`].
+    Airdrop,
+    /// Transfers from a treasury account, which pays the transfer fee as well.
+    Treasury(Arc<dyn TransactionSigner>),
+}
+
+#[derive(Clone)]
+pub struct BalanceMonitorConfig {
+    pub interval: Duration,
+    /// New attestations are refused below this balance, in lamports.
+    pub min_balance: u64,
+    /// Payer is topped up below this balance, in lamports.
+    pub warn_balance: u64,
+    /// Balance to top up to, in lamports.
+    pub target_balance: u64,
+    pub top_up: TopUp,
+}
+
+impl Default for BalanceMonitorConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60),
+            min_balance: LAMPORTS_PER_SOL / 20,
+            warn_balance: LAMPORTS_PER_SOL / 2,
+            target_balance: 2 * LAMPORTS_PER_SOL,
+            top_up: TopUp::Disabled,
+        }
+    }
+}
+
+/// Returned instead of sending a transaction when payer can't afford it.
+#[derive(Debug, Clone, Copy)]
+pub struct InsufficientFunds {
+    pub balance: u64,
+    pub required: u64,
+}
+
+impl fmt::Display for InsufficientFunds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "payer balance of {} lamports is below the required {}",
+            self.balance, self.required
+        )
+    }
+}
+
+impl std::error::Error for InsufficientFunds {}
+
+const SECS_PER_DAY: u64 = 60 * 60 * 24;
+
+/// Returned instead of sending a transaction once payer reached its daily spend cap.
+#[derive(Debug, Clone, Copy)]
+pub struct SpendCapReached {
+    pub spent: u64,
+    pub cap: u64,
+    /// Until the cap resets at UTC midnight.
+    pub resets_in: Duration,
+}
+
+impl fmt::Display for SpendCapReached {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "payer spent {} of its daily {} lamports, resets in {}s",
+            self.spent,
+            self.cap,
+            self.resets_in.as_secs()
+        )
+    }
+}
+
+impl std::error::Error for SpendCapReached {}
+
+impl SpendCapReached {
+    /// `spent` out of `cap` in the current UTC day.
+    pub fn today(spent: u64, cap: u64) -> Self {
+        Self {
+            spent,
+            cap,
+            resets_in: Duration::from_secs(SECS_PER_DAY - now_secs() % SECS_PER_DAY),
+        }
+    }
+}
+
+/// Counts what payer spends per UTC day, so that it stops short of a cap.
+/// See [`AttestationService::with_spend_cap`].
+#[async_trait]
+pub trait SpendCap: Send + Sync {
+    /// Counts `lamports` towards today's spend before a transaction is sent,
+    /// or fails with [`SpendCapReached`] if they would go over the cap.
+    async fn reserve(&self, lamports: u64) -> Result<()>;
+
+    /// Takes back a reservation of a transaction that failed, as it most likely didn't land.
+    async fn release(&self, lamports: u64) -> Result<()>;
+}
+
+/// Lamports payer spent during a UTC day, counted in days since epoch.
+#[derive(Debug, Default)]
+struct DailySpend {
+    day: u64,
+    lamports: u64,
+}
+
+/// [`SpendCap`] kept in memory, so it resets with the process.
+pub(crate) struct MemorySpendCap {
+    cap: u64,
+    spent: Mutex<DailySpend>,
+}
+
+impl MemorySpendCap {
+    pub fn new(cap: u64) -> Self {
+        Self {
+            cap,
+            spent: Mutex::default(),
+        }
+    }
+
+    /// Today's spend, reset if it was counted on another day.
+    fn today(&self) -> std::sync::MutexGuard<'_, DailySpend> {
+        let today = now_secs() / SECS_PER_DAY;
+        let mut spent = self.spent.lock().unwrap();
+        if spent.day != today {
+            *spent = DailySpend {
+                day: today,
+                lamports: 0,
+            };
+        }
+        spent
+    }
+}
+
+#[async_trait]
+impl SpendCap for MemorySpendCap {
+    async fn reserve(&self, lamports: u64) -> Result<()> {
+        let mut spent = self.today();
+        if spent.lamports.saturating_add(lamports) > self.cap {
+            return Err(SpendCapReached::today(spent.lamports, self.cap).into());
+        }
+        spent.lamports += lamports;
+        Ok(())
+    }
+
+    async fn release(&self, lamports: u64) -> Result<()> {
+        let mut spent = self.today();
+        spent.lamports = spent.lamports.saturating_sub(lamports);
+        Ok(())
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+impl AttestationService {
+    /// Last payer balance in lamports seen by [`Self::watch_payer_balance`], if it runs.
+    pub fn payer_balance(&self) -> Option<u64> {
+        match self.payer_balance.load(Ordering::Relaxed) {
+            u64::MAX => None,
+            balance => Some(balance),
+        }
+    }
+
+    /// Checks payer balance every `config.interval`, topping it up when it runs low.
+    /// Never returns, so it's meant to be spawned as a background task.
+    pub async fn watch_payer_balance(&self, config: BalanceMonitorConfig) {
+        self.min_payer_balance
+            .store(config.min_balance, Ordering::Relaxed);
+
+        let mut interval = tokio::time::interval(config.interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            interval.tick().await;
+            if let Err(err) = self.check_payer_balance(&config).await {
+                warn!(%err, "couldn't check payer balance");
+            }
+        }
+    }
+
+    async fn check_payer_balance(&self, config: &BalanceMonitorConfig) -> Result<()> {
+        let mut balance = self.rpc.get_balance(&self.payer.pubkey()).await?;
+        self.record_payer_balance(balance);
+        debug!(balance, "checked payer balance");
+
+        if balance >= config.warn_balance {
+            return Ok(());
+        }
+        warn!(
+            balance,
+            threshold = config.warn_balance,
+            "payer balance is running low"
+        );
+
+        let amount = config.target_balance.saturating_sub(balance);
+        let sig = match &config.top_up {
+            TopUp::Disabled => return Ok(()),
+            TopUp::Airdrop => self.airdrop(amount).await?,
+            TopUp::Treasury(treasury) => self.transfer_from(treasury.as_ref(), amount).await?,
+        };
+        balance = self.rpc.get_balance(&self.payer.pubkey()).await?;
+        self.record_payer_balance(balance);
+        info!(%sig, amount, balance, "topped up payer");
+
+        Ok(())
+    }
+
+    pub(crate) fn record_payer_balance(&self, balance: u64) {
+        self.payer_balance.store(balance, Ordering::Relaxed);
+        gauge!(telemetry::PAYER_BALANCE).set(balance as f64);
+    }
+
+    /// Errors with [`InsufficientFunds`] if the last seen payer balance is below the minimum.
+    /// The daily spend cap is checked as transactions are sent, see [`Self::reserve_spend`].
+    pub fn ensure_funds(&self) -> Result<()> {
+        let required = self.min_payer_balance.load(Ordering::Relaxed);
+        if let Some(balance) = self.payer_balance() {
+            if balance < required {
+                return Err(InsufficientFunds { balance, required }.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Counts `lamports` towards the daily spend cap, failing with [`SpendCapReached`]
+    /// if they don't fit. Transactions paid by payer are counted as they are sent,
+    /// but ones sent by other clients paying from the same account have to reserve here,
+    /// for the cap to hold.
+    pub async fn reserve_spend(&self, lamports: u64) -> Result<()> {
+        let Some(cap) = &self.spend_cap else {
+            return Ok(());
+        };
+        cap.reserve(lamports).await?;
+        debug!(lamports, "reserved payer spend");
+        Ok(())
+    }
+
+    /// Takes back [`Self::reserve_spend`] for a transaction that was never sent.
+    pub async fn release_spend(&self, lamports: u64) {
+        let Some(cap) = &self.spend_cap else {
+            return;
+        };
+        if let Err(err) = cap.release(lamports).await {
+            warn!(%err, lamports, "couldn't release payer spend");
+        }
+    }
+
+    /// What `tx` costs payer if it pays the fee: signatures, priority fee, and rent of
+    /// attestations it creates. Rent of other accounts isn't counted.
+    pub(crate) fn estimate_spend(&self, tx: &VersionedTransaction) -> u64 {
+        let keys = tx.message.static_account_keys();
+        if keys.first() != Some(&self.payer.pubkey()) {
+            return 0;
+        }
+        let signatures = u64::from(tx.message.header().num_required_signatures);
+        let priority_fee = (COMPUTE_UNIT_LIMIT as u64 * COMPUTE_UNIT_PRICE).div_ceil(1_000_000);
+        let attestations = tx
+            .message
+            .instructions()
+            .iter()
+            .filter(|ix| {
+                ix.program_id(keys) == &SOLANA_ATTESTATION_SERVICE_ID
+                    && ix.data.first() == Some(&CREATE_ATTESTATION_DISCRIMINATOR)
+            })
+            .count() as u64;
+        let rent = Rent::default().minimum_balance(ATTESTATION_ACCOUNT_SIZE);
+        signatures * LAMPORTS_PER_SIGNATURE + priority_fee + attestations * rent
+    }
+
+    pub(crate) async fn airdrop(&self, lamports: u64) -> Result<Signature> {
+        if !self.cluster.is_test_cluster() {
+            return Err(anyhow!("refusing to airdrop on {}", self.cluster));
+        }
+        let sig = self
+            .rpc
+            .request_airdrop(&self.payer.pubkey(), lamports)
+            .await?;
+        confirm::confirm_signature(&self.rpc, sig, self.progress.as_ref()).await?;
+        Ok(sig)
+    }
+
+    async fn transfer_from(
+        &self,
+        treasury: &dyn TransactionSigner,
+        lamports: u64,
+    ) -> Result<Signature> {
+        let instruction = transfer(&treasury.pubkey(), &self.payer.pubkey(), lamports);
+        let bh = self.rpc.get_latest_blockhash().await?;
+        let mut tx = VersionedTransaction {
+            signatures: vec![],
+            message: self.compile_message(treasury.pubkey(), None, vec![instruction], bh)?,
+        };
+        transaction::partial_sign(&mut tx, &[treasury]).await?;
+        self.submit_transaction(&tx).await
+    }
+}
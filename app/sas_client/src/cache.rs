@@ -0,0 +1,138 @@
+use std::{
+    pin::pin,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use futures_util::StreamExt;
+use metrics::counter;
+use moka::{sync::Cache, Expiry};
+use solana_sdk::pubkey::Pubkey;
+use tracing::{debug, info, warn};
+
+use crate::{
+    telemetry::{CACHE_INVALIDATIONS, CACHE_REQUESTS},
+    AttestationAccount, AttestationService,
+};
+
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(5);
+
+/// How [`AttestationService::fetch_attestation`] results are cached.
+#[derive(Debug, Clone)]
+pub struct AttestationCacheConfig {
+    /// How long an attestation is cached, unless it expires sooner.
+    pub ttl: Duration,
+    /// How long an absent attestation is cached. Keep it short, as attestations
+    /// can be created without this service knowing, e.g. user-paid ones.
+    pub negative_ttl: Duration,
+    pub max_entries: u64,
+}
+
+impl Default for AttestationCacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(60),
+            negative_ttl: Duration::from_secs(5),
+            max_entries: 100_000,
+        }
+    }
+}
+
+/// Attestation accounts by PDA, `None` for ones that don't exist.
+pub(crate) struct AttestationCache {
+    entries: Cache<Pubkey, Entry>,
+    config: AttestationCacheConfig,
+}
+
+#[derive(Clone)]
+struct Entry {
+    account: Option<AttestationAccount>,
+    ttl: Duration,
+}
+
+struct EntryExpiry;
+
+impl Expiry<Pubkey, Entry> for EntryExpiry {
+    fn expire_after_create(&self, _: &Pubkey, entry: &Entry, _: Instant) -> Option<Duration> {
+        Some(entry.ttl)
+    }
+}
+
+impl AttestationCache {
+    pub fn new(config: AttestationCacheConfig) -> Self {
+        let entries = Cache::builder()
+            .max_capacity(config.max_entries)
+            .expire_after(EntryExpiry)
+            .build();
+        Self { entries, config }
+    }
+
+    /// `None` on a miss, `Some(None)` if the attestation is known not to exist.
+    pub fn get(&self, pda: &Pubkey) -> Option<Option<AttestationAccount>> {
+        let entry = self.entries.get(pda);
+        let result = if entry.is_some() { "hit" } else { "miss" };
+        counter!(CACHE_REQUESTS, "result" => result).increment(1);
+        entry.map(|entry| entry.account)
+    }
+
+    /// Caches `account` no longer than until it expires on-chain.
+    pub fn insert(&self, pda: Pubkey, account: Option<AttestationAccount>) {
+        let ttl = match &account {
+            Some(account) => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |now| now.as_secs() as i64);
+                let remaining =
+                    Duration::from_secs(account.expiry.saturating_sub(now).max(0) as u64);
+                self.config.ttl.min(remaining)
+            }
+            None => self.config.negative_ttl,
+        };
+        if !ttl.is_zero() {
+            self.entries.insert(pda, Entry { account, ttl });
+        }
+    }
+
+    pub fn invalidate(&self, pda: &Pubkey, reason: &'static str) {
+        self.entries.invalidate(pda);
+        counter!(CACHE_INVALIDATIONS, "reason" => reason).increment(1);
+    }
+
+    fn invalidate_all(&self, reason: &'static str) {
+        self.entries.invalidate_all();
+        counter!(CACHE_INVALIDATIONS, "reason" => reason).increment(1);
+    }
+}
+
+impl AttestationService {
+    /// Drops cached attestations as soon as their accounts change on-chain, by subscribing to
+    /// attestations of this credential and schema over websocket. Closed accounts don't match
+    /// the subscription filters anymore, so attestations revoked elsewhere live out their TTL.
+    ///
+    /// Never returns, so it's meant to be spawned as a background task.
+    /// Does nothing if the cache is disabled.
+    pub async fn watch_attestation_changes(&self) {
+        let Some(cache) = &self.cache else {
+            return;
+        };
+        loop {
+            if let Err(err) = self.invalidate_on_notifications(cache).await {
+                warn!(%err, "attestation subscription failed");
+            }
+            // Changes might have been missed while disconnected.
+            cache.invalidate_all("resubscribe");
+            tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+        }
+    }
+
+    async fn invalidate_on_notifications(&self, cache: &AttestationCache) -> anyhow::Result<()> {
+        let mut updates = pin!(self.subscribe_all().await?);
+        info!("subscribed to attestation changes");
+
+        while let Some(update) = updates.next().await {
+            let pda = update.pda();
+            debug!(%pda, "attestation changed");
+            cache.invalidate(&pda, "subscription");
+        }
+        Err(anyhow::anyhow!("attestation subscription closed"))
+    }
+}
@@ -0,0 +1,110 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use url::Url;
+
+/// Solana cluster the service talks to.
+///
+/// Decides the default RPC/WS endpoints, whether airdrops are available,
+/// and how strict the service is about keys (see [`Self::is_test_cluster`]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cluster {
+    Localnet,
+    Devnet,
+    Testnet,
+    Mainnet,
+    /// Arbitrary endpoints, e.g. a private RPC provider.
+    /// Treated as mainnet, as there is no telling what it points to, unless they override
+    /// a named test cluster's endpoints, see [`crate::ServiceConfig::rpc_url`].
+    Custom {
+        rpc_url: String,
+        ws_url: String,
+        test_cluster: bool,
+    },
+}
+
+impl Cluster {
+    pub fn rpc_url(&self) -> &str {
+        match self {
+            Self::Localnet => "http://127.0.0.1:8899",
+            Self::Devnet => "https://api.devnet.solana.com",
+            Self::Testnet => "https://api.testnet.solana.com",
+            Self::Mainnet => "https://api.mainnet-beta.solana.com",
+            Self::Custom { rpc_url, .. } => rpc_url,
+        }
+    }
+
+    pub fn ws_url(&self) -> &str {
+        match self {
+            Self::Localnet => "ws://127.0.0.1:8900",
+            Self::Devnet => "wss://api.devnet.solana.com",
+            Self::Testnet => "wss://api.testnet.solana.com",
+            Self::Mainnet => "wss://api.mainnet-beta.solana.com",
+            Self::Custom { ws_url, .. } => ws_url,
+        }
+    }
+
+    /// Clusters where SOL is free: airdrops work, and test setups
+    /// (e.g. one keypair for payer, issuer and signer) are allowed.
+    pub fn is_test_cluster(&self) -> bool {
+        match self {
+            Self::Localnet | Self::Devnet | Self::Testnet => true,
+            Self::Mainnet => false,
+            Self::Custom { test_cluster, .. } => *test_cluster,
+        }
+    }
+
+    /// Custom endpoints at `rpc_url`, with websocket URL derived the way Solana CLI does:
+    /// `http(s)` becomes `ws(s)`, and an explicit port is incremented.
+    pub fn custom(rpc_url: &str) -> Result<Self> {
+        let mut ws_url = Url::parse(rpc_url)?;
+        let scheme = match ws_url.scheme() {
+            "http" => "ws",
+            "https" => "wss",
+            scheme => return Err(anyhow!("unsupported RPC URL scheme {scheme:?}")),
+        };
+        ws_url
+            .set_scheme(scheme)
+            .map_err(|()| anyhow!("couldn't derive websocket URL from {rpc_url}"))?;
+        if let Some(port) = ws_url.port() {
+            ws_url
+                .set_port(Some(port + 1))
+                .map_err(|()| anyhow!("couldn't derive websocket URL from {rpc_url}"))?;
+        }
+
+        Ok(Self::Custom {
+            rpc_url: rpc_url.to_string(),
+            ws_url: ws_url.to_string(),
+            test_cluster: false,
+        })
+    }
+}
+
+impl FromStr for Cluster {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "localnet" | "localhost" => Ok(Self::Localnet),
+            "devnet" => Ok(Self::Devnet),
+            "testnet" => Ok(Self::Testnet),
+            "mainnet" | "mainnet-beta" => Ok(Self::Mainnet),
+            url if url.contains("://") => Self::custom(url),
+            other => Err(anyhow!(
+                "unknown cluster {other:?}, expected localnet, devnet, testnet, mainnet or an RPC URL"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Cluster {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Localnet => write!(f, "localnet"),
+            Self::Devnet => write!(f, "devnet"),
+            Self::Testnet => write!(f, "testnet"),
+            Self::Mainnet => write!(f, "mainnet"),
+            Self::Custom { rpc_url, .. } => write!(f, "{rpc_url}"),
+        }
+    }
+}
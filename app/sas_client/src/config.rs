@@ -0,0 +1,331 @@
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use solana_sdk::{
+    commitment_config::{CommitmentConfig, CommitmentLevel},
+    pubkey::Pubkey,
+};
+
+use crate::{
+    read_keypair, AttestationCacheConfig, AttestationService, BalanceMonitorConfig, Cluster,
+    OfflineSigner, RemoteSigner, TopUp, TransactionSigner, ATTESTATION_EXPIRY,
+};
+
+/// Everything [`AttestationService`] needs, deserializable from a config file.
+/// See [`Self::apply_env`] for overriding it with environment variables.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServiceConfig {
+    /// Cluster name or RPC URL, see [`Cluster`].
+    pub cluster: String,
+    /// Overrides cluster RPC endpoint. Makes the cluster [`Cluster::Custom`],
+    /// which is still a test cluster if `cluster` is one.
+    pub rpc_url: Option<String>,
+    /// Overrides cluster websocket endpoint, like `rpc_url`.
+    pub ws_url: Option<String>,
+    pub commitment: CommitmentLevel,
+    /// Keypair file of the account paying for transactions and rent.
+    pub payer: Option<PathBuf>,
+    pub issuer: SignerConfig,
+    pub signer: SignerConfig,
+    pub attestation_expiry_secs: u64,
+    pub balance: BalanceConfig,
+    pub cache: CacheConfig,
+}
+
+impl Default for ServiceConfig {
+    fn default() -> Self {
+        Self {
+            cluster: Cluster::Localnet.to_string(),
+            rpc_url: None,
+            ws_url: None,
+            commitment: CommitmentLevel::Confirmed,
+            payer: None,
+            issuer: SignerConfig::default(),
+            signer: SignerConfig::default(),
+            attestation_expiry_secs: ATTESTATION_EXPIRY.as_secs(),
+            balance: BalanceConfig::default(),
+            cache: CacheConfig::default(),
+        }
+    }
+}
+
+/// Where a signing key lives. The first one set wins:
+/// `url` (with `pubkey` and optional `token`) is a [`RemoteSigner`],
+/// `creds` is a keypair file, and `pubkey` alone is an [`OfflineSigner`],
+/// see [`AttestationService::signs_offline`].
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SignerConfig {
+    pub url: Option<String>,
+    pub token: Option<String>,
+    pub creds: Option<PathBuf>,
+    pub pubkey: Option<String>,
+}
+
+/// Lamport thresholds of [`BalanceMonitorConfig`], plus an optional treasury to top up from
+/// and an optional daily spend cap, see [`AttestationService::with_daily_spend_cap`].
+/// Applications may count it elsewhere with [`AttestationService::with_spend_cap`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BalanceConfig {
+    pub interval_secs: u64,
+    pub min: u64,
+    pub warn: u64,
+    pub target: u64,
+    /// Keypair file to top up payer from. Without it, payer is airdropped on test clusters.
+    pub treasury: Option<PathBuf>,
+    pub daily_spend_cap: Option<u64>,
+}
+
+impl Default for BalanceConfig {
+    fn default() -> Self {
+        let defaults = BalanceMonitorConfig::default();
+        Self {
+            interval_secs: defaults.interval.as_secs(),
+            min: defaults.min_balance,
+            warn: defaults.warn_balance,
+            target: defaults.target_balance,
+            treasury: None,
+            daily_spend_cap: None,
+        }
+    }
+}
+
+/// [`AttestationCacheConfig`], plus whether to keep it fresh with
+/// [`AttestationService::watch_attestation_changes`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// `0` disables the cache.
+    pub ttl_secs: u64,
+    pub negative_ttl_secs: u64,
+    pub max_entries: u64,
+    pub subscribe: bool,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        let defaults = AttestationCacheConfig::default();
+        Self {
+            ttl_secs: defaults.ttl.as_secs(),
+            negative_ttl_secs: defaults.negative_ttl.as_secs(),
+            max_entries: defaults.max_entries,
+            subscribe: false,
+        }
+    }
+}
+
+impl ServiceConfig {
+    /// Overrides fields with whichever of these variables are set:
+    /// `CLUSTER`, `RPC_URL`, `WS_URL`, `COMMITMENT`, `PAYER_CREDS`, `ATTESTATION_EXPIRY_SECS`,
+    /// `{ISSUER,SIGNER}_{URL,TOKEN,CREDS,PUBKEY}`, `PAYER_{MIN,WARN,TARGET}_BALANCE`,
+    /// `TREASURY_CREDS`, `PAYER_DAILY_SPEND_CAP`, `ATTESTATION_CACHE_TTL_SECS`
+    /// and `ATTESTATION_CACHE_SUBSCRIBE`.
+    pub fn apply_env(&mut self) -> Result<()> {
+        self.apply_vars(|name| std::env::var(name).ok())
+    }
+
+    /// [`Self::apply_env`] with variables looked up by `var` instead,
+    /// e.g. from a map in tests, which can't set the process environment safely.
+    pub fn apply_vars(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<()> {
+        let parse = |name: &str, value: String| -> Result<u64> {
+            value
+                .parse()
+                .with_context(|| format!("{name} has to be a number, got {value:?}"))
+        };
+
+        if let Some(cluster) = var("CLUSTER") {
+            self.cluster = cluster;
+        }
+        self.rpc_url = var("RPC_URL").or(self.rpc_url.take());
+        self.ws_url = var("WS_URL").or(self.ws_url.take());
+        if let Some(commitment) = var("COMMITMENT") {
+            self.commitment = commitment
+                .parse()
+                .map_err(|_| anyhow!("COMMITMENT has to be processed, confirmed or finalized"))?;
+        }
+        self.payer = var("PAYER_CREDS").map(PathBuf::from).or(self.payer.take());
+        if let Some(expiry) = var("ATTESTATION_EXPIRY_SECS") {
+            self.attestation_expiry_secs = parse("ATTESTATION_EXPIRY_SECS", expiry)?;
+        }
+
+        for (prefix, signer) in [("ISSUER", &mut self.issuer), ("SIGNER", &mut self.signer)] {
+            let var = |name: &str| var(&format!("{prefix}_{name}"));
+            signer.url = var("URL").or(signer.url.take());
+            signer.token = var("TOKEN").or(signer.token.take());
+            signer.creds = var("CREDS").map(PathBuf::from).or(signer.creds.take());
+            signer.pubkey = var("PUBKEY").or(signer.pubkey.take());
+        }
+
+        for (name, threshold) in [
+            ("PAYER_MIN_BALANCE", &mut self.balance.min),
+            ("PAYER_WARN_BALANCE", &mut self.balance.warn),
+            ("PAYER_TARGET_BALANCE", &mut self.balance.target),
+        ] {
+            if let Some(lamports) = var(name) {
+                *threshold = parse(name, lamports)?;
+            }
+        }
+        self.balance.treasury = var("TREASURY_CREDS")
+            .map(PathBuf::from)
+            .or(self.balance.treasury.take());
+        if let Some(cap) = var("PAYER_DAILY_SPEND_CAP") {
+            self.balance.daily_spend_cap = Some(parse("PAYER_DAILY_SPEND_CAP", cap)?);
+        }
+        if let Some(ttl) = var("ATTESTATION_CACHE_TTL_SECS") {
+            self.cache.ttl_secs = parse("ATTESTATION_CACHE_TTL_SECS", ttl)?;
+        }
+        if let Some(subscribe) = var("ATTESTATION_CACHE_SUBSCRIBE") {
+            self.cache.subscribe = subscribe.parse().with_context(|| {
+                format!("ATTESTATION_CACHE_SUBSCRIBE has to be a bool, got {subscribe:?}")
+            })?;
+        }
+
+        Ok(())
+    }
+
+    pub fn cluster(&self) -> Result<Cluster> {
+        let cluster: Cluster = self.cluster.parse().context("invalid cluster")?;
+        if self.rpc_url.is_none() && self.ws_url.is_none() {
+            return Ok(cluster);
+        }
+
+        let rpc_url = self
+            .rpc_url
+            .clone()
+            .unwrap_or_else(|| cluster.rpc_url().to_string());
+        let ws_url = match &self.ws_url {
+            Some(ws_url) => ws_url.clone(),
+            None => Cluster::custom(&rpc_url)
+                .context("invalid rpc_url")?
+                .ws_url()
+                .to_string(),
+        };
+        Ok(Cluster::Custom {
+            rpc_url,
+            ws_url,
+            test_cluster: cluster.is_test_cluster(),
+        })
+    }
+
+    pub fn attestation_expiry(&self) -> Result<Duration> {
+        if self.attestation_expiry_secs == 0 {
+            return Err(anyhow!("attestation_expiry_secs has to be positive"));
+        }
+        Ok(Duration::from_secs(self.attestation_expiry_secs))
+    }
+
+    /// `None` if the cache is disabled.
+    pub fn attestation_cache(&self) -> Option<AttestationCacheConfig> {
+        let CacheConfig {
+            ttl_secs,
+            negative_ttl_secs,
+            max_entries,
+            subscribe: _,
+        } = self.cache;
+        (ttl_secs > 0).then(|| AttestationCacheConfig {
+            ttl: Duration::from_secs(ttl_secs),
+            negative_ttl: Duration::from_secs(negative_ttl_secs),
+            max_entries,
+        })
+    }
+
+    /// Doesn't include [`TopUp::Airdrop`], as it depends on the cluster.
+    pub fn balance_monitor(&self) -> Result<BalanceMonitorConfig> {
+        let BalanceConfig {
+            interval_secs,
+            min,
+            warn,
+            target,
+            ref treasury,
+            daily_spend_cap: _,
+        } = self.balance;
+        if interval_secs == 0 {
+            return Err(anyhow!("balance.interval_secs has to be positive"));
+        }
+        if !(min <= warn && warn <= target) {
+            return Err(anyhow!(
+                "balance thresholds have to satisfy min <= warn <= target, got {min}, {warn}, {target}"
+            ));
+        }
+
+        let top_up = match treasury {
+            Some(path) => {
+                TopUp::Treasury(Arc::new(read_keypair(path).with_context(|| {
+                    format!("couldn't read treasury {}", path.display())
+                })?))
+            }
+            None => TopUp::Disabled,
+        };
+        Ok(BalanceMonitorConfig {
+            interval: Duration::from_secs(interval_secs),
+            min_balance: min,
+            warn_balance: warn,
+            target_balance: target,
+            top_up,
+        })
+    }
+}
+
+impl SignerConfig {
+    pub fn load(&self, name: &str) -> Result<Arc<dyn TransactionSigner>> {
+        let pubkey = || -> Result<Pubkey> {
+            let pubkey = self
+                .pubkey
+                .as_deref()
+                .ok_or_else(|| anyhow!("{name}.pubkey is required"))?;
+            pubkey
+                .parse()
+                .with_context(|| format!("{name}.pubkey {pubkey:?} isn't a valid pubkey"))
+        };
+
+        if let Some(url) = &self.url {
+            let mut signer = RemoteSigner::new(url, pubkey()?);
+            if let Some(token) = &self.token {
+                signer = signer.with_token(token);
+            }
+            return Ok(Arc::new(signer));
+        }
+        if let Some(path) = &self.creds {
+            let keypair = read_keypair(path)
+                .with_context(|| format!("couldn't read {name} keypair {}", path.display()))?;
+            return Ok(Arc::new(keypair));
+        }
+        if self.pubkey.is_some() {
+            return Ok(Arc::new(OfflineSigner(pubkey()?)));
+        }
+        Err(anyhow!("{name} needs one of url, creds or pubkey"))
+    }
+}
+
+impl AttestationService {
+    /// Validates `config` and builds a service out of it.
+    pub fn from_config(config: &ServiceConfig) -> Result<Self> {
+        let payer = config
+            .payer
+            .as_ref()
+            .ok_or_else(|| anyhow!("payer keypair path is required"))?;
+        let payer = read_keypair(payer)
+            .with_context(|| format!("couldn't read payer keypair {}", payer.display()))?;
+
+        let mut service = Self::new(
+            config.cluster()?,
+            Arc::new(payer),
+            config.issuer.load("issuer")?,
+            config.signer.load("signer")?,
+        )
+        .with_commitment(CommitmentConfig {
+            commitment: config.commitment,
+        })
+        .with_attestation_expiry(config.attestation_expiry()?);
+        if let Some(cap) = config.balance.daily_spend_cap {
+            service = service.with_daily_spend_cap(cap);
+        }
+        if let Some(cache) = config.attestation_cache() {
+            service = service.with_attestation_cache(cache);
+        }
+        Ok(service)
+    }
+}
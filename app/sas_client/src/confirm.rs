@@ -0,0 +1,166 @@
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use metrics::{counter, histogram};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{signature::Signature, transaction::TransactionError};
+use tracing::{debug, info, warn};
+
+use crate::telemetry::{CONFIRMATION_DURATION, TRANSACTIONS};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+const CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A step of transaction confirmation, reported to a [`ProgressCallback`].
+#[derive(Debug, Clone, Copy)]
+pub enum ConfirmationProgress {
+    /// Transaction was accepted by the RPC node.
+    Sent { signature: Signature },
+    /// Transaction landed, but hasn't reached the desired commitment yet.
+    Pending {
+        signature: Signature,
+        confirmations: Option<usize>,
+    },
+    /// Transaction reached the desired commitment.
+    Confirmed {
+        signature: Signature,
+        elapsed: Duration,
+    },
+}
+
+/// A transaction that was sent, but failed on-chain or wasn't confirmed in time.
+/// It still may land in the latter case, so its signature is worth checking later.
+#[derive(Debug, Clone)]
+pub struct TransactionFailed {
+    pub signature: Signature,
+    /// Why it failed on-chain, `None` if it wasn't confirmed in time.
+    pub err: Option<TransactionError>,
+    pub reason: String,
+}
+
+impl TransactionFailed {
+    fn failed(signature: Signature, err: TransactionError) -> Self {
+        Self {
+            signature,
+            reason: format!("failed: {err}"),
+            err: Some(err),
+        }
+    }
+
+    pub fn timed_out(&self) -> bool {
+        self.err.is_none()
+    }
+}
+
+impl fmt::Display for TransactionFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "transaction {} {}", self.signature, self.reason)
+    }
+}
+
+impl std::error::Error for TransactionFailed {}
+
+/// Gets notified about confirmation progress, e.g. to draw a spinner in CLI contexts.
+pub type ProgressCallback = Arc<dyn Fn(&ConfirmationProgress) + Send + Sync>;
+
+/// Polls signature status until it satisfies the client's commitment,
+/// the transaction fails, or [`CONFIRMATION_TIMEOUT`] passes.
+/// Returns the slot the transaction landed in.
+pub(crate) async fn confirm_signature(
+    rpc: &RpcClient,
+    signature: Signature,
+    progress: Option<&ProgressCallback>,
+) -> Result<u64> {
+    let report = |step: ConfirmationProgress| {
+        if let Some(progress) = progress {
+            progress(&step);
+        }
+    };
+    report(ConfirmationProgress::Sent { signature });
+    debug!(%signature, "transaction sent, awaiting confirmation");
+
+    let started = Instant::now();
+    let mut last_confirmations = None;
+    while started.elapsed() < CONFIRMATION_TIMEOUT {
+        let status = rpc
+            .get_signature_statuses(&[signature])
+            .await?
+            .value
+            .pop()
+            .flatten();
+
+        if let Some(status) = status {
+            if let Some(err) = status.err {
+                warn!(%signature, %err, "transaction failed");
+                counter!(TRANSACTIONS, "status" => "failed").increment(1);
+                return Err(TransactionFailed::failed(signature, err).into());
+            }
+            if status.satisfies_commitment(rpc.commitment()) {
+                let elapsed = started.elapsed();
+                debug!(%signature, ?elapsed, "transaction confirmed");
+                counter!(TRANSACTIONS, "status" => "confirmed").increment(1);
+                histogram!(CONFIRMATION_DURATION).record(elapsed);
+                report(ConfirmationProgress::Confirmed { signature, elapsed });
+                return Ok(status.slot);
+            }
+            if last_confirmations != Some(status.confirmations) {
+                last_confirmations = Some(status.confirmations);
+                debug!(%signature, confirmations = ?status.confirmations, "transaction pending");
+                report(ConfirmationProgress::Pending {
+                    signature,
+                    confirmations: status.confirmations,
+                });
+            }
+        }
+
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+
+    warn!(%signature, "transaction confirmation timed out");
+    counter!(TRANSACTIONS, "status" => "timed_out").increment(1);
+    Err(TransactionFailed {
+        signature,
+        err: None,
+        reason: format!("wasn't confirmed in {CONFIRMATION_TIMEOUT:?}"),
+    }
+    .into())
+}
+
+/// Checks once more on a transaction [`confirm_signature`] timed out on with `err`,
+/// as it may have landed since. Returns its signature and slot if it did,
+/// otherwise `err`, or the on-chain failure if it landed and failed.
+pub(crate) async fn recheck_timed_out(
+    rpc: &RpcClient,
+    err: anyhow::Error,
+) -> Result<(Signature, u64)> {
+    let Some(signature) = err
+        .downcast_ref::<TransactionFailed>()
+        .filter(|failed| failed.timed_out())
+        .map(|failed| failed.signature)
+    else {
+        return Err(err);
+    };
+    let status = match rpc.get_signature_statuses(&[signature]).await {
+        Ok(statuses) => statuses.value.into_iter().next().flatten(),
+        Err(rpc_err) => {
+            warn!(%signature, %rpc_err, "couldn't recheck timed out transaction");
+            return Err(err);
+        }
+    };
+    let Some(status) = status else {
+        return Err(err);
+    };
+    if let Some(tx_err) = status.err {
+        warn!(%signature, %tx_err, "timed out transaction failed");
+        return Err(TransactionFailed::failed(signature, tx_err).into());
+    }
+    if !status.satisfies_commitment(rpc.commitment()) {
+        return Err(err);
+    }
+    info!(%signature, "timed out transaction landed");
+    Ok((signature, status.slot))
+}
@@ -0,0 +1,100 @@
+use std::{
+    sync::atomic::Ordering,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{AttestationService, TransactionSigner};
+
+/// Outcome of a single readiness check.
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub ok: bool,
+    /// What was observed, or why the check failed.
+    pub detail: String,
+}
+
+impl From<Result<String>> for Check {
+    fn from(result: Result<String>) -> Self {
+        match result {
+            Ok(detail) => Self { ok: true, detail },
+            Err(err) => Self {
+                ok: false,
+                detail: format!("{err:#}"),
+            },
+        }
+    }
+}
+
+/// Whether the service can issue attestations right now, check by check.
+#[derive(Debug, Clone, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub rpc: Check,
+    pub payer_balance: Check,
+    pub credential: Check,
+    pub schema: Check,
+}
+
+impl AttestationService {
+    /// Runs every readiness check concurrently.
+    /// RPC is considered stale if its latest slot is older than `max_slot_age`.
+    pub async fn readiness(&self, max_slot_age: Duration) -> Readiness {
+        let (rpc, payer_balance, credential, schema) = tokio::join!(
+            self.check_rpc(max_slot_age),
+            self.check_payer_balance_above_min(),
+            self.check_account(self.cred_pda),
+            self.check_account(self.schema_pda),
+        );
+        let (rpc, payer_balance, credential, schema) = (
+            Check::from(rpc),
+            Check::from(payer_balance),
+            Check::from(credential),
+            Check::from(schema),
+        );
+        Readiness {
+            ready: rpc.ok && payer_balance.ok && credential.ok && schema.ok,
+            rpc,
+            payer_balance,
+            credential,
+            schema,
+        }
+    }
+
+    async fn check_rpc(&self, max_slot_age: Duration) -> Result<String> {
+        self.rpc.get_health().await?;
+        let slot = self.rpc.get_slot().await?;
+        let block_time = self.rpc.get_block_time(slot).await?;
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let age = now.saturating_sub(block_time).max(0) as u64;
+        if age > max_slot_age.as_secs() {
+            return Err(anyhow!(
+                "slot {slot} is {age}s old, more than {}s",
+                max_slot_age.as_secs()
+            ));
+        }
+        Ok(format!("slot {slot} is {age}s old"))
+    }
+
+    async fn check_payer_balance_above_min(&self) -> Result<String> {
+        let balance = self.rpc.get_balance(&self.payer.pubkey()).await?;
+        self.record_payer_balance(balance);
+
+        let min = self.min_payer_balance.load(Ordering::Relaxed);
+        if balance < min {
+            return Err(anyhow!("{balance} lamports, less than {min}"));
+        }
+        Ok(format!("{balance} lamports"))
+    }
+
+    async fn check_account(&self, address: Pubkey) -> Result<String> {
+        if !self.account_exists(address).await? {
+            return Err(anyhow!("{address} doesn't exist"));
+        }
+        Ok(address.to_string())
+    }
+}
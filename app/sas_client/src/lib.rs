@@ -1,44 +1,87 @@
 use anyhow::{anyhow, Result};
 use std::{
     error::Error,
+    fmt,
+    sync::{atomic::AtomicU64, Arc},
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 use tracing::{
     debug, debug_span,
     field::{self},
-    info, warn, Instrument,
+    info, instrument, warn, Instrument, Span,
 };
 
 use borsh::{BorshDeserialize, BorshSerialize};
+use metrics::counter;
+use solana_account_decoder_client_types::UiAccountEncoding;
+use solana_address_lookup_table_interface::{
+    instruction::{create_lookup_table, extend_lookup_table},
+    state::AddressLookupTable,
+};
 use solana_attestation_service_client::{
     accounts::Attestation,
-    instructions::{CreateAttestationBuilder, CreateCredentialBuilder, CreateSchemaBuilder},
+    instructions::{
+        CloseAttestationBuilder, CreateAttestationBuilder, CreateCredentialBuilder,
+        CreateSchemaBuilder,
+    },
     programs::SOLANA_ATTESTATION_SERVICE_ID,
 };
 use solana_client::{
     client_error::{ClientError, ClientErrorKind},
     nonblocking::rpc_client::RpcClient,
-    rpc_request::RpcError,
+    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+    rpc_filter::{Memcmp, RpcFilterType},
+    rpc_request::{RpcError, RpcResponseErrorData},
 };
 use solana_sdk::{
     commitment_config::CommitmentConfig,
     compute_budget::ComputeBudgetInstruction,
-    instruction::Instruction,
-    message::Message,
+    hash::Hash,
+    instruction::{Instruction, InstructionError},
+    message::{v0, AddressLookupTableAccount, VersionedMessage},
     native_token::LAMPORTS_PER_SOL,
     pubkey::Pubkey,
-    signature::{read_keypair_file, Keypair, Signature},
-    signer::Signer,
-    transaction::Transaction,
+    signature::{Keypair, Signature},
+    transaction::{TransactionError, VersionedTransaction},
+};
+use solana_system_interface::{error::SystemError, program};
+
+mod balance;
+mod cache;
+mod cluster;
+mod config;
+mod confirm;
+mod health;
+mod nonce;
+mod signer;
+mod subscribe;
+pub mod telemetry;
+pub mod transaction;
+use balance::MemorySpendCap;
+pub use balance::{
+    BalanceMonitorConfig, InsufficientFunds, SpendCap, SpendCapReached, TopUp,
+    LAMPORTS_PER_SIGNATURE,
 };
-use solana_system_interface::program;
+use cache::AttestationCache;
+pub use cache::AttestationCacheConfig;
+pub use cluster::Cluster;
+pub use config::{BalanceConfig, CacheConfig, ServiceConfig, SignerConfig};
+pub use confirm::{ConfirmationProgress, ProgressCallback, TransactionFailed};
+pub use health::{Check, Readiness};
+pub use signer::{read_keypair, OfflineSigner, RemoteSigner, TransactionSigner};
+pub use subscribe::AttestationUpdate;
 
 pub const CREDENTIAL_NAME: &str = "Test Credential";
 pub const SCHEMA_NAME: &str = "UserVerification";
 pub const SCHEMA_VERSION: u8 = 1;
 pub const SCHEMA_DESC: &str = "age: bool, country: bool";
-const ATTESTATION_EXPIRY: Duration = Duration::from_secs(60 * 60 * 24 * 30);
+pub(crate) const ATTESTATION_EXPIRY: Duration = Duration::from_secs(60 * 60 * 24 * 30);
 const MIN_SOL_BALANCE: u32 = 2;
+const COMPUTE_UNIT_LIMIT: u32 = 400_000;
+/// Priority fee, in micro-lamports per compute unit.
+const COMPUTE_UNIT_PRICE: u64 = 1;
+/// Credential pubkey in attestation account data, after the discriminator and nonce.
+const ATTESTATION_CREDENTIAL_OFFSET: usize = 1 + 32;
 
 #[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default)]
 pub struct AttestationPayload {
@@ -55,45 +98,166 @@ impl AttestationPayload {
     }
 }
 
+/// An attestation created by [`AttestationService::create_attestation`].
+#[derive(Debug, Clone, Copy)]
+pub struct IssuedAttestation {
+    pub pda: Pubkey,
+    pub signature: Signature,
+    /// Slot the transaction landed in.
+    pub slot: u64,
+    /// Unix timestamp the attestation is valid until.
+    pub expiry: i64,
+}
+
+/// [`AttestationService::create_attestation`] found the attestation already created,
+/// e.g. by a concurrent request for the same user. Safe to treat as success.
+#[derive(Debug, Clone, Copy)]
+pub struct AttestationExists {
+    pub pda: Pubkey,
+}
+
+impl fmt::Display for AttestationExists {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "attestation {} already exists", self.pda)
+    }
+}
+
+impl Error for AttestationExists {}
+
+/// An attestation closed by [`AttestationService::revoke_attestation`].
+#[derive(Debug, Clone, Copy)]
+pub struct RevokedAttestation {
+    pub pda: Pubkey,
+    pub signature: Signature,
+    pub slot: u64,
+}
+
+/// An attestation account of this service's credential and schema, as it is on-chain.
+#[derive(Debug, Clone)]
+pub struct AttestationAccount {
+    pub pda: Pubkey,
+    pub user: Pubkey,
+    pub payload: AttestationPayload,
+    pub expiry: i64,
+}
+
 pub struct AttestationService {
+    cluster: Cluster,
     rpc: RpcClient,
-    payer: Keypair,
-    issuer: Keypair,
-    signer: Keypair,
+    /// Shared with other clients paying from the same account, see [`Self::payer`].
+    payer: Arc<Keypair>,
+    issuer: Arc<dyn TransactionSigner>,
+    signer: Arc<dyn TransactionSigner>,
+
+    progress: Option<ProgressCallback>,
+    lookup_table: Option<AddressLookupTableAccount>,
+    attestation_expiry: Duration,
+    /// Lamports, `u64::MAX` until first checked.
+    payer_balance: AtomicU64,
+    min_payer_balance: AtomicU64,
+    spend_cap: Option<Arc<dyn SpendCap>>,
+    cache: Option<AttestationCache>,
 
     pub cred_pda: Pubkey,
     pub schema_pda: Pubkey,
 }
 
 impl AttestationService {
-    pub fn new(rpc_url: &str, payer: Keypair, issuer: Keypair, signer: Keypair) -> Self {
-        let rpc =
-            RpcClient::new_with_commitment(rpc_url.to_string(), CommitmentConfig::confirmed());
+    pub fn new(
+        cluster: Cluster,
+        payer: Arc<Keypair>,
+        issuer: Arc<dyn TransactionSigner>,
+        signer: Arc<dyn TransactionSigner>,
+    ) -> Self {
+        let rpc = telemetry::metered_rpc_client(cluster.rpc_url(), CommitmentConfig::confirmed());
         let cred_pda = Self::credential_pda(issuer.pubkey());
         let schema_pda = Self::schema_pda(cred_pda);
         Self {
+            cluster,
             rpc,
             payer,
             issuer,
             signer,
+            progress: None,
+            lookup_table: None,
+            attestation_expiry: ATTESTATION_EXPIRY,
+            payer_balance: AtomicU64::new(u64::MAX),
+            min_payer_balance: AtomicU64::new(0),
+            spend_cap: None,
+            cache: None,
             cred_pda,
             schema_pda,
         }
     }
 
-    /// Airdrops some SOL to payer, so that a min threshold is passed,
-    /// and tries to create credential and schema accounts if not already present.
+    /// Reports transaction confirmation progress to `progress`, e.g. for CLI output.
+    pub fn with_progress(mut self, progress: ProgressCallback) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Refuses transactions paid by payer that would take its spend on fees and attestation
+    /// rent in a UTC day over `lamports`. Counted in memory, see [`Self::with_spend_cap`]
+    /// for keeping count elsewhere.
+    pub fn with_daily_spend_cap(self, lamports: u64) -> Self {
+        self.with_spend_cap(Arc::new(MemorySpendCap::new(lamports)))
+    }
+
+    /// Counts payer spend with `cap`, e.g. in a database so that restarts don't reset it.
+    pub fn with_spend_cap(mut self, cap: Arc<dyn SpendCap>) -> Self {
+        self.spend_cap = Some(cap);
+        self
+    }
+
+    /// Caches attestation lookups in memory, see [`Self::watch_attestation_changes`]
+    /// for dropping them once they change on-chain.
+    pub fn with_attestation_cache(mut self, config: AttestationCacheConfig) -> Self {
+        self.cache = Some(AttestationCache::new(config));
+        self
+    }
+
+    pub fn with_commitment(mut self, commitment: CommitmentConfig) -> Self {
+        self.rpc = telemetry::metered_rpc_client(self.cluster.rpc_url(), commitment);
+        self
+    }
+
+    /// How long created attestations stay valid, 30 days by default.
+    pub fn with_attestation_expiry(mut self, expiry: Duration) -> Self {
+        self.attestation_expiry = expiry;
+        self
+    }
+
+    /// Compiles every transaction against `table`, so that its addresses take one byte
+    /// instead of 32. See [`Self::create_lookup_table`].
+    pub fn with_lookup_table(mut self, table: AddressLookupTableAccount) -> Self {
+        self.lookup_table = Some(table);
+        self
+    }
+
+    pub fn cluster(&self) -> &Cluster {
+        &self.cluster
+    }
+
+    /// On test clusters, airdrops some SOL to payer, so that a min threshold is passed.
+    /// Elsewhere, makes sure test keys aren't used.
+    /// Then tries to create credential and schema accounts if not already present.
     pub async fn init(&mut self) -> Result<()> {
-        let balance = self.airdrop_up_to(MIN_SOL_BALANCE).await?;
-        debug!(
-            %balance,
-            "airdropped sol to payer if needed"
-        );
+        if self.cluster.is_test_cluster() {
+            let balance = self.airdrop_up_to(MIN_SOL_BALANCE).await?;
+            debug!(
+                %balance,
+                "airdropped sol to payer if needed"
+            );
+        } else {
+            self.ensure_distinct_keys()?;
+        }
         if !self.account_exists(self.cred_pda).await? {
+            self.ensure_issuer_signs("credential", self.cred_pda)?;
             let sig = self.create_credential().await?;
             debug!(%sig, "created new credential");
         }
         if !self.account_exists(self.schema_pda).await? {
+            self.ensure_issuer_signs("schema", self.schema_pda)?;
             let sig = self.create_schema().await?;
             debug!(%sig, "created new schema");
         }
@@ -101,6 +265,12 @@ impl AttestationService {
         Ok(())
     }
 
+    /// Whether attestations have to be built unsigned and signed externally,
+    /// see [`Self::build_attestation`].
+    pub fn signs_offline(&self) -> bool {
+        self.signer.signs_offline()
+    }
+
     /// [`Self::init`], but for using on a clean localnet.
     pub async fn init_unchecked(&mut self) -> Result<()> {
         self.airdrop_up_to(MIN_SOL_BALANCE).await?;
@@ -109,13 +279,12 @@ impl AttestationService {
         Ok(())
     }
 
+    /// [`Self::from_config`] with defaults overridden by environment variables,
+    /// see [`ServiceConfig::apply_env`].
     pub fn try_from_env() -> std::result::Result<Self, Box<dyn Error>> {
-        Ok(Self::new(
-            &std::env::var("RPC_URL")?,
-            read_keypair_file(&std::env::var("PAYER_CREDS")?)?,
-            read_keypair_file(&std::env::var("ISSUER_CREDS")?)?,
-            read_keypair_file(&std::env::var("SIGNER_CREDS")?)?,
-        ))
+        let mut config = ServiceConfig::default();
+        config.apply_env()?;
+        Ok(Self::from_config(&config)?)
     }
 }
 
@@ -135,31 +304,121 @@ impl AttestationService {
     async fn send(
         &self,
         instruction: Instruction,
-        extra_signers: &[&Keypair],
+        extra_signers: &[&dyn TransactionSigner],
     ) -> Result<Signature> {
-        let mut signers: Vec<&Keypair> = vec![&self.payer];
-        signers.extend_from_slice(extra_signers);
+        let (sig, _) = self
+            .send_instructions(vec![instruction], extra_signers)
+            .await?;
+        Ok(sig)
+    }
 
-        let msg = Message::new(
-            &[
-                ComputeBudgetInstruction::set_compute_unit_limit(400_000),
-                ComputeBudgetInstruction::set_compute_unit_price(1),
-                instruction,
-            ],
-            Some(&self.payer.pubkey()),
-        );
+    /// Sends `instructions` in a single v0 transaction paid by payer.
+    /// Returns its signature and the slot it landed in.
+    async fn send_instructions(
+        &self,
+        instructions: Vec<Instruction>,
+        extra_signers: &[&dyn TransactionSigner],
+    ) -> Result<(Signature, u64)> {
+        let mut signers: Vec<&dyn TransactionSigner> = vec![self.payer.as_ref()];
+        signers.extend_from_slice(extra_signers);
 
         let bh = self.rpc.get_latest_blockhash().await?;
-        let tx = Transaction::new(&signers, msg, bh);
-        let sig = self
-            .rpc
-            .send_and_confirm_transaction_with_spinner(&tx)
-            .await?;
+        let mut tx = VersionedTransaction {
+            signatures: vec![],
+            message: self.compile_message(self.payer.pubkey(), None, instructions, bh)?,
+        };
+        transaction::partial_sign(&mut tx, &signers).await?;
+        self.submit(&tx).await
+    }
+
+    /// Sends `instructions` in a transaction signed by payer alone, e.g. to invoke
+    /// a program that reads attestations.
+    pub async fn send_as_payer(&self, instructions: Vec<Instruction>) -> Result<Signature> {
+        let (sig, _) = self.send_instructions(instructions, &[]).await?;
+        Ok(sig)
+    }
+
+    /// Submits a transaction that was signed elsewhere, e.g. one built with
+    /// [`Self::build_durable_attestation`].
+    pub async fn submit_transaction(&self, tx: &VersionedTransaction) -> Result<Signature> {
+        let (sig, _) = self.submit(tx).await?;
         Ok(sig)
     }
 
-    pub fn payer(&self) -> Keypair {
-        self.payer.insecure_clone() // kinda bad, but it's for a different service
+    /// Sends `tx` and waits for confirmation, returning the slot it landed in too.
+    #[instrument(name = "transaction.submit", skip_all, fields(signature = field::Empty))]
+    async fn submit(&self, tx: &VersionedTransaction) -> Result<(Signature, u64)> {
+        let missing = transaction::missing_signers(tx);
+        if !missing.is_empty() {
+            return Err(anyhow!("transaction is missing signatures of {missing:?}"));
+        }
+        let spend = self.estimate_spend(tx);
+        self.reserve_spend(spend).await?;
+        let sig = match self.rpc.send_transaction(tx).await {
+            Ok(sig) => sig,
+            // Rejected before it got anywhere, e.g. by preflight, so nothing was spent.
+            Err(err) => {
+                self.release_spend(spend).await;
+                return Err(err.into());
+            }
+        };
+        Span::current().record("signature", field::display(sig));
+        // The reservation stays from here on: a failed transaction is still charged fees,
+        // and one that wasn't confirmed in time may land later.
+        let slot = confirm::confirm_signature(&self.rpc, sig, self.progress.as_ref()).await?;
+        Ok((sig, slot))
+    }
+
+    /// Compiles a v0 message paid by `fee_payer`, with compute budget set.
+    /// `advance_nonce` has to go first for durable nonce transactions.
+    fn compile_message(
+        &self,
+        fee_payer: Pubkey,
+        advance_nonce: Option<Instruction>,
+        instructions: Vec<Instruction>,
+        recent_blockhash: Hash,
+    ) -> Result<VersionedMessage> {
+        let mut ixs: Vec<Instruction> = advance_nonce.into_iter().collect();
+        ixs.extend([
+            ComputeBudgetInstruction::set_compute_unit_limit(COMPUTE_UNIT_LIMIT),
+            ComputeBudgetInstruction::set_compute_unit_price(COMPUTE_UNIT_PRICE),
+        ]);
+        ixs.extend(instructions);
+
+        let msg = v0::Message::try_compile(
+            &fee_payer,
+            &ixs,
+            self.lookup_table.as_slice(),
+            recent_blockhash,
+        )?;
+        Ok(VersionedMessage::V0(msg))
+    }
+
+    /// Compiles `instructions` into a transaction signed only by payer.
+    /// Required signatures of issuer/signer are left for external signing.
+    async fn build_transaction(
+        &self,
+        advance_nonce: Option<Instruction>,
+        instructions: Vec<Instruction>,
+        recent_blockhash: Hash,
+    ) -> Result<VersionedTransaction> {
+        let mut tx = VersionedTransaction {
+            signatures: vec![],
+            message: self.compile_message(
+                self.payer.pubkey(),
+                advance_nonce,
+                instructions,
+                recent_blockhash,
+            )?,
+        };
+        transaction::partial_sign(&mut tx, &[self.payer.as_ref()]).await?;
+        Ok(tx)
+    }
+
+    /// A handle to the payer keypair, e.g. for an anchor client paying from the same account.
+    /// The secret itself is never copied and gets zeroized once the last handle is dropped.
+    pub fn payer(&self) -> Arc<Keypair> {
+        Arc::clone(&self.payer)
     }
 
     pub fn credential_pda(issuer: Pubkey) -> Pubkey {
@@ -196,6 +455,23 @@ impl AttestationService {
         .0
     }
 
+    /// Fails if any of payer, issuer and signer share a key. Using one keypair for all three
+    /// is fine for tests, but has no place outside of test clusters.
+    fn ensure_distinct_keys(&self) -> Result<()> {
+        let (payer, issuer, signer) = (
+            self.payer.pubkey(),
+            self.issuer.pubkey(),
+            self.signer.pubkey(),
+        );
+        if payer == issuer || payer == signer || issuer == signer {
+            return Err(anyhow!(
+                "payer, issuer and signer have to be distinct keys on {}",
+                self.cluster
+            ));
+        }
+        Ok(())
+    }
+
     /// On success, returns factual balance in lamperts after possible airdrop.
     /// It should be no less than `amount_sol`.
     async fn airdrop_up_to(&self, amount_sol: u32) -> Result<u64> {
@@ -205,35 +481,63 @@ impl AttestationService {
             return Ok(balance);
         }
 
-        let sig = self
-            .rpc
-            .request_airdrop(&self.payer.pubkey(), (amount_lamperts - balance) as u64)
-            .await?;
-        self.rpc
-            .confirm_transaction_with_spinner(
-                &sig,
-                &self.rpc.get_latest_blockhash().await?,
-                CommitmentConfig::confirmed(),
-            )
-            .await?;
+        self.airdrop(amount_lamperts - balance).await?;
         Ok(amount_lamperts)
     }
 
+    /// An offline issuer can't create `account` here, it has to be built with
+    /// [`Self::build_credential`] or [`Self::build_schema`] and submitted once signed.
+    fn ensure_issuer_signs(&self, account: &str, pda: Pubkey) -> Result<()> {
+        if self.issuer.signs_offline() {
+            return Err(anyhow!(
+                "{account} {pda} doesn't exist, and issuer {} signs offline: \
+                build it with `build_{account}`, sign it and submit it first",
+                self.issuer.pubkey()
+            ));
+        }
+        Ok(())
+    }
+
     async fn create_credential(&self) -> Result<Signature> {
-        let instruction = CreateCredentialBuilder::new()
+        self.send(
+            self.create_credential_instruction(),
+            &[self.issuer.as_ref()],
+        )
+        .await
+    }
+
+    async fn create_schema(&self) -> Result<Signature> {
+        self.send(self.create_schema_instruction(), &[self.issuer.as_ref()])
+            .await
+    }
+
+    /// Unsigned counterpart of credential creation, for an issuer that signs offline.
+    pub async fn build_credential(&self) -> Result<VersionedTransaction> {
+        let bh = self.rpc.get_latest_blockhash().await?;
+        self.build_transaction(None, vec![self.create_credential_instruction()], bh)
+            .await
+    }
+
+    /// Unsigned counterpart of schema creation, for an issuer that signs offline.
+    pub async fn build_schema(&self) -> Result<VersionedTransaction> {
+        let bh = self.rpc.get_latest_blockhash().await?;
+        self.build_transaction(None, vec![self.create_schema_instruction()], bh)
+            .await
+    }
+
+    fn create_credential_instruction(&self) -> Instruction {
+        CreateCredentialBuilder::new()
             .payer(self.payer.pubkey())
             .credential(self.cred_pda)
             .authority(self.issuer.pubkey())
             .system_program(program::id())
             .name(CREDENTIAL_NAME.to_string())
             .signers(vec![self.signer.pubkey()])
-            .instruction();
-
-        self.send(instruction, &[&self.issuer]).await
+            .instruction()
     }
 
-    async fn create_schema(&self) -> Result<Signature> {
-        let instruction = CreateSchemaBuilder::new()
+    fn create_schema_instruction(&self) -> Instruction {
+        CreateSchemaBuilder::new()
             .payer(self.payer.pubkey())
             .authority(self.issuer.pubkey())
             .credential(self.cred_pda)
@@ -247,30 +551,317 @@ impl AttestationService {
                     .into_iter()
                     .collect(),
             )
-            .instruction();
+            .instruction()
+    }
+}
 
-        self.send(instruction, &[&self.issuer]).await
+impl AttestationService {
+    /// Creates an empty lookup table owned by payer and extends it with
+    /// [`Self::lookup_table_addresses`]. Returns the table address.
+    ///
+    /// The table only becomes usable a slot after its last extension, so it is
+    /// better created once and passed to [`Self::with_lookup_table`] on later runs.
+    pub async fn create_lookup_table(&self) -> Result<Pubkey> {
+        let recent_slot = self.rpc.get_slot().await?;
+        let (instruction, table) =
+            create_lookup_table(self.payer.pubkey(), self.payer.pubkey(), recent_slot);
+        let sig = self.send(instruction, &[]).await?;
+        debug!(%sig, %table, "created lookup table");
+
+        self.extend_lookup_table(table, self.lookup_table_addresses())
+            .await?;
+        Ok(table)
+    }
+
+    /// Appends `addresses` to a lookup table owned by payer.
+    pub async fn extend_lookup_table(&self, table: Pubkey, addresses: Vec<Pubkey>) -> Result<()> {
+        let instruction = extend_lookup_table(
+            table,
+            self.payer.pubkey(),
+            Some(self.payer.pubkey()),
+            addresses,
+        );
+        let sig = self.send(instruction, &[]).await?;
+        debug!(%sig, %table, "extended lookup table");
+        Ok(())
+    }
+
+    /// Fetches a lookup table, e.g. for [`Self::with_lookup_table`].
+    pub async fn fetch_lookup_table(&self, table: Pubkey) -> Result<AddressLookupTableAccount> {
+        let account = self.rpc.get_account(&table).await?;
+        let state = AddressLookupTable::deserialize(&account.data)
+            .map_err(|err| anyhow!("couldn't parse lookup table {table}: {err}"))?;
+        Ok(AddressLookupTableAccount {
+            key: table,
+            addresses: state.addresses.to_vec(),
+        })
+    }
+
+    /// Non-signer accounts shared by every attestation instruction.
+    /// Signers and invoked programs can't be loaded from a lookup table.
+    pub fn lookup_table_addresses(&self) -> Vec<Pubkey> {
+        vec![self.cred_pda, self.schema_pda, program::id()]
     }
 }
 
 impl AttestationService {
+    /// Fails with [`AttestationExists`] if `user` already has an attestation.
     pub async fn create_attestation(
         &self,
         user: Pubkey,
         payload: AttestationPayload,
-    ) -> Result<Pubkey> {
-        let mut data = Vec::with_capacity(2);
-        payload.serialize(&mut data)?;
+    ) -> Result<IssuedAttestation> {
+        self.ensure_funds()?;
+        let expiry = self.expiry_from_now();
+        let (attestation_pda, instruction) = self.create_attestation_instruction(
+            self.payer.pubkey(),
+            user,
+            payload.clone(),
+            expiry,
+        )?;
+        debug!(?instruction);
+
+        let sent = match self
+            .send_instructions(vec![instruction], &[self.signer.as_ref()])
+            .await
+        {
+            // It may have landed after confirmation gave up on it.
+            Err(err) => confirm::recheck_timed_out(&self.rpc, err).await,
+            landed => landed,
+        };
+        let (signature, slot) = match sent {
+            Ok(landed) => landed,
+            Err(err) if self.attestation_exists_after(&err, attestation_pda).await => {
+                debug!(%attestation_pda, %err, "attestation already exists");
+                if let Some(cache) = &self.cache {
+                    cache.invalidate(&attestation_pda, "exists");
+                }
+                return Err(AttestationExists {
+                    pda: attestation_pda,
+                }
+                .into());
+            }
+            Err(err) => return Err(err),
+        };
+        counter!(telemetry::ATTESTATIONS_CREATED).increment(1);
+        if let Some(cache) = &self.cache {
+            let account = AttestationAccount {
+                pda: attestation_pda,
+                user,
+                payload,
+                expiry,
+            };
+            cache.insert(attestation_pda, Some(account));
+        }
+
+        Ok(IssuedAttestation {
+            pda: attestation_pda,
+            signature,
+            slot,
+            expiry,
+        })
+    }
+
+    /// Whether creating the attestation at `pda` failed with `err` because it already exists:
+    /// either simulation said so, or the transaction failed on-chain as the account
+    /// is already in use. A timed out transaction doesn't count, as it may be ours.
+    async fn attestation_exists_after(&self, err: &anyhow::Error, pda: Pubkey) -> bool {
+        if let Some(ClientError {
+            kind:
+                ClientErrorKind::RpcError(RpcError::RpcResponseError {
+                    data: RpcResponseErrorData::SendTransactionPreflightFailure(simulation),
+                    ..
+                }),
+            ..
+        }) = err.downcast_ref::<ClientError>()
+        {
+            return simulation
+                .logs
+                .iter()
+                .flatten()
+                .any(|log| log.contains("already in use"));
+        }
+        let Some(TransactionFailed {
+            err: Some(TransactionError::InstructionError(_, InstructionError::Custom(code))),
+            ..
+        }) = err.downcast_ref::<TransactionFailed>()
+        else {
+            return false;
+        };
+        *code == SystemError::AccountAlreadyInUse as u32
+            && self.account_exists(pda).await.unwrap_or(false)
+    }
+
+    /// Closes the attestation of `user`, refunding its rent to payer.
+    pub async fn revoke_attestation(&self, user: Pubkey) -> Result<RevokedAttestation> {
+        let (attestation_pda, instruction) = self.close_attestation_instruction(user);
+
+        let (signature, slot) = self
+            .send_instructions(vec![instruction], &[self.signer.as_ref()])
+            .await?;
+        info!(%attestation_pda, %user, %signature, "revoked attestation");
+        if let Some(cache) = &self.cache {
+            cache.invalidate(&attestation_pda, "revoked");
+        }
+
+        Ok(RevokedAttestation {
+            pda: attestation_pda,
+            signature,
+            slot,
+        })
+    }
+
+    /// Closes the attestation of `user` and creates it again with `payload` and a fresh expiry,
+    /// as SAS can't extend one in place. Both happen in one transaction, so that the user
+    /// is never left without an attestation.
+    pub async fn renew_attestation(
+        &self,
+        user: Pubkey,
+        payload: AttestationPayload,
+    ) -> Result<IssuedAttestation> {
+        self.ensure_funds()?;
+        let expiry = self.expiry_from_now();
+        let (attestation_pda, close) = self.close_attestation_instruction(user);
+        let (_, create) = self.create_attestation_instruction(
+            self.payer.pubkey(),
+            user,
+            payload.clone(),
+            expiry,
+        )?;
+
+        let (signature, slot) = self
+            .send_instructions(vec![close, create], &[self.signer.as_ref()])
+            .await?;
+        info!(%attestation_pda, %user, %signature, expiry, "renewed attestation");
+        counter!(telemetry::ATTESTATIONS_CREATED).increment(1);
+        if let Some(cache) = &self.cache {
+            let account = AttestationAccount {
+                pda: attestation_pda,
+                user,
+                payload,
+                expiry,
+            };
+            cache.insert(attestation_pda, Some(account));
+        }
+
+        Ok(IssuedAttestation {
+            pda: attestation_pda,
+            signature,
+            slot,
+            expiry,
+        })
+    }
+
+    /// Unsigned counterpart of [`Self::create_attestation`]: the returned transaction
+    /// is signed by payer only and has to be signed by signer before submission.
+    pub async fn build_attestation(
+        &self,
+        user: Pubkey,
+        payload: AttestationPayload,
+    ) -> Result<VersionedTransaction> {
+        let (_, instruction) = self.create_attestation_instruction(
+            self.payer.pubkey(),
+            user,
+            payload,
+            self.expiry_from_now(),
+        )?;
+        let bh = self.rpc.get_latest_blockhash().await?;
+        self.build_transaction(None, vec![instruction], bh).await
+    }
+
+    /// Builds an attestation transaction where `user` pays both the fee and the account rent
+    /// instead of payer. It comes signed by signer, and has to be signed by the user's wallet
+    /// and submitted before its blockhash expires.
+    pub async fn build_user_paid_attestation(
+        &self,
+        user: Pubkey,
+        payload: AttestationPayload,
+    ) -> Result<(Pubkey, VersionedTransaction)> {
+        let (attestation_pda, instruction) =
+            self.create_attestation_instruction(user, user, payload, self.expiry_from_now())?;
+        let bh = self.rpc.get_latest_blockhash().await?;
+
+        let mut tx = VersionedTransaction {
+            signatures: vec![],
+            message: self.compile_message(user, None, vec![instruction], bh)?,
+        };
+        transaction::partial_sign(&mut tx, &[self.signer.as_ref()]).await?;
+        debug!(%attestation_pda, %user, "built user-paid attestation transaction");
+
+        Ok((attestation_pda, tx))
+    }
 
-        let expiry = (SystemTime::now() + ATTESTATION_EXPIRY)
+    /// Attests every user in `batch` within a single transaction, returning attestation PDAs
+    /// in the same order. The batch has to fit into one transaction, which takes a lookup table
+    /// for anything more than a handful of users.
+    pub async fn create_attestations(
+        &self,
+        batch: Vec<(Pubkey, AttestationPayload)>,
+    ) -> Result<Vec<Pubkey>> {
+        self.ensure_funds()?;
+        let (pdas, instructions): (Vec<_>, Vec<_>) = batch
+            .into_iter()
+            .map(|(user, payload)| {
+                self.create_attestation_instruction(
+                    self.payer.pubkey(),
+                    user,
+                    payload,
+                    self.expiry_from_now(),
+                )
+            })
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .unzip();
+        debug!(count = instructions.len(), "attesting batch");
+
+        _ = self
+            .send_instructions(instructions, &[self.signer.as_ref()])
+            .await?;
+        counter!(telemetry::ATTESTATIONS_CREATED).increment(pdas.len() as u64);
+        if let Some(cache) = &self.cache {
+            for pda in &pdas {
+                cache.invalidate(pda, "created");
+            }
+        }
+
+        Ok(pdas)
+    }
+
+    /// Unix timestamp an attestation created now expires at.
+    fn expiry_from_now(&self) -> i64 {
+        (SystemTime::now() + self.attestation_expiry)
             .duration_since(UNIX_EPOCH)
             .unwrap()
-            .as_secs() as i64;
+            .as_secs() as i64
+    }
+
+    /// Returns attestation PDA and the instruction closing it, refunding its rent to payer.
+    fn close_attestation_instruction(&self, user: Pubkey) -> (Pubkey, Instruction) {
+        let attestation_pda = Self::attestation_pda(self.cred_pda, self.schema_pda, user);
+        let instruction = CloseAttestationBuilder::new()
+            .payer(self.payer.pubkey())
+            .authority(self.signer.pubkey())
+            .credential(self.cred_pda)
+            .attestation(attestation_pda)
+            .instruction();
+        (attestation_pda, instruction)
+    }
+
+    /// Returns attestation PDA and the instruction creating it, with rent paid by `rent_payer`.
+    fn create_attestation_instruction(
+        &self,
+        rent_payer: Pubkey,
+        user: Pubkey,
+        payload: AttestationPayload,
+        expiry: i64,
+    ) -> Result<(Pubkey, Instruction)> {
+        let mut data = Vec::with_capacity(2);
+        payload.serialize(&mut data)?;
 
         let attestation_pda = Self::attestation_pda(self.cred_pda, self.schema_pda, user);
 
         let instruction = CreateAttestationBuilder::new()
-            .payer(self.payer.pubkey())
+            .payer(rent_payer)
             .authority(self.signer.pubkey())
             .credential(self.cred_pda)
             .schema(self.schema_pda)
@@ -279,27 +870,60 @@ impl AttestationService {
             .nonce(user)
             .expiry(expiry)
             .instruction();
-        debug!(?instruction);
-
-        _ = self.send(instruction, &[&self.signer]).await?;
 
-        Ok(attestation_pda)
+        Ok((attestation_pda, instruction))
     }
 
+    /// `None` if `user` has no attestation. Unlike that, RPC failures are errors.
+    /// Served from cache if it's enabled, see [`Self::with_attestation_cache`].
     pub async fn fetch_attestation(&self, user: Pubkey) -> Result<Option<AttestationPayload>> {
+        let account = self.fetch_attestation_account(user).await?;
+        Ok(account.map(|account| account.payload))
+    }
+
+    /// [`Self::fetch_attestation`], with the rest of the account.
+    pub async fn fetch_attestation_account(
+        &self,
+        user: Pubkey,
+    ) -> Result<Option<AttestationAccount>> {
+        if let Some(cached) = self.cached_attestation(user) {
+            return Ok(cached);
+        }
+        self.refresh_attestation(user).await
+    }
+
+    /// Cached attestation of `user`, without falling back to RPC on a miss.
+    /// `Some(None)` if it's known not to exist.
+    fn cached_attestation(&self, user: Pubkey) -> Option<Option<AttestationAccount>> {
         let attestation_pda = Self::attestation_pda(self.cred_pda, self.schema_pda, user);
+        self.cache.as_ref()?.get(&attestation_pda)
+    }
 
+    /// Fetches the attestation of `user` bypassing the cache, and caches it.
+    async fn refresh_attestation(&self, user: Pubkey) -> Result<Option<AttestationAccount>> {
+        let attestation_pda = Self::attestation_pda(self.cred_pda, self.schema_pda, user);
+        let account = self.get_attestation(attestation_pda).await?;
+        if let Some(cache) = &self.cache {
+            cache.insert(attestation_pda, account.clone());
+        }
+        Ok(account)
+    }
+
+    async fn get_attestation(&self, attestation_pda: Pubkey) -> Result<Option<AttestationAccount>> {
         let span = debug_span!("attestation.get", pda = %attestation_pda, success = field::Empty);
-        let Ok(acc) = self
+        let acc = self
             .rpc
-            .get_account(&attestation_pda)
+            .get_account_with_commitment(&attestation_pda, self.rpc.commitment())
             .instrument(span.clone())
             .await
-        else {
-            span.record("success", true);
+            .inspect_err(|_| {
+                span.record("success", false);
+            })?
+            .value;
+        span.record("success", true);
+        let Some(acc) = acc else {
             return Ok(None);
         };
-        span.record("success", true);
 
         let span = debug_span!("attestation.parse.header",
             pda = %attestation_pda,
@@ -335,6 +959,67 @@ impl AttestationService {
             }
         };
 
-        Ok(Some(payload))
+        Ok(Some(AttestationAccount {
+            pda: attestation_pda,
+            user: attestation.nonce,
+            payload,
+            expiry: attestation.expiry,
+        }))
     }
+
+    /// Every attestation of this service's credential and schema, via `getProgramAccounts`.
+    /// Expired ones are included, as they stay on-chain until closed.
+    pub async fn list_attestations(&self) -> Result<Vec<AttestationAccount>> {
+        let accounts = self
+            .rpc
+            .get_program_accounts_with_config(
+                &SOLANA_ATTESTATION_SERVICE_ID,
+                self.attestation_accounts_config(),
+            )
+            .await?;
+        debug!(count = accounts.len(), "listed attestations");
+
+        accounts
+            .into_iter()
+            .map(|(pda, account)| decode_attestation(pda, &account.data))
+            .collect()
+    }
+
+    /// Selects attestation accounts of this service's credential and schema.
+    pub(crate) fn attestation_accounts_config(&self) -> RpcProgramAccountsConfig {
+        // Attestation layout: discriminator, nonce, credential, schema, ...
+        let filters = vec![
+            RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                ATTESTATION_CREDENTIAL_OFFSET,
+                self.cred_pda.as_ref(),
+            )),
+            RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                ATTESTATION_CREDENTIAL_OFFSET + 32,
+                self.schema_pda.as_ref(),
+            )),
+        ];
+        RpcProgramAccountsConfig {
+            filters: Some(filters),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                commitment: Some(self.rpc.commitment()),
+                ..RpcAccountInfoConfig::default()
+            },
+            ..RpcProgramAccountsConfig::default()
+        }
+    }
+}
+
+/// Parses attestation account data, header and payload.
+pub(crate) fn decode_attestation(pda: Pubkey, data: &[u8]) -> Result<AttestationAccount> {
+    let attestation = Attestation::from_bytes(data)
+        .map_err(|err| anyhow!("couldn't parse attestation {pda}: {err}"))?;
+    let payload = AttestationPayload::try_from_slice(&attestation.data)
+        .map_err(|err| anyhow!("couldn't decode payload of {pda}: {err}"))?;
+    Ok(AttestationAccount {
+        pda,
+        user: attestation.nonce,
+        payload,
+        expiry: attestation.expiry,
+    })
 }
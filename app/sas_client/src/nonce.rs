@@ -0,0 +1,88 @@
+use anyhow::{anyhow, Result};
+use solana_client::nonblocking::nonce_utils;
+use solana_nonce::state::State as NonceState;
+use solana_sdk::{
+    hash::Hash,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    transaction::VersionedTransaction,
+};
+use solana_system_interface::instruction::{advance_nonce_account, create_nonce_account};
+use tracing::debug;
+
+use crate::{AttestationPayload, AttestationService, TransactionSigner};
+
+impl AttestationService {
+    /// Creates a durable nonce account at `nonce`, funded by payer and advanced by `authority`.
+    pub async fn create_nonce_account(
+        &self,
+        nonce: &Keypair,
+        authority: Pubkey,
+    ) -> Result<Signature> {
+        let lamports = self
+            .rpc
+            .get_minimum_balance_for_rent_exemption(NonceState::size())
+            .await?;
+        let instructions =
+            create_nonce_account(&self.payer.pubkey(), &nonce.pubkey(), &authority, lamports);
+
+        let (sig, _) = self.send_instructions(instructions, &[nonce]).await?;
+        debug!(%sig, nonce = %nonce.pubkey(), %authority, "created nonce account");
+        Ok(sig)
+    }
+
+    /// Advances `nonce`, invalidating every transaction built against its current value.
+    pub async fn advance_nonce_account(
+        &self,
+        nonce: Pubkey,
+        authority: &dyn TransactionSigner,
+    ) -> Result<Signature> {
+        let instruction = advance_nonce_account(&nonce, &authority.pubkey());
+        let sig = self.send(instruction, &[authority]).await?;
+        debug!(%sig, %nonce, "advanced nonce account");
+        Ok(sig)
+    }
+
+    /// Current durable nonce value, used in place of a recent blockhash.
+    pub async fn fetch_nonce(&self, nonce: Pubkey) -> Result<Hash> {
+        let account =
+            nonce_utils::get_account_with_commitment(&self.rpc, &nonce, self.rpc.commitment())
+                .await?;
+        let data = nonce_utils::data_from_account(&account)
+            .map_err(|err| anyhow!("couldn't read nonce account {nonce}: {err}"))?;
+        Ok(data.blockhash())
+    }
+
+    /// Builds an attestation transaction that stays valid until `nonce` is advanced,
+    /// so that it can be signed offline and submitted later via [`Self::submit_transaction`].
+    ///
+    /// Only payer signs here. The attestation signer and `nonce_authority` are expected to
+    /// add their signatures with [`crate::transaction::partial_sign`]. Attestation expiry is counted
+    /// from the moment of building, not submission.
+    pub async fn build_durable_attestation(
+        &self,
+        user: Pubkey,
+        payload: AttestationPayload,
+        nonce: Pubkey,
+        nonce_authority: Pubkey,
+    ) -> Result<VersionedTransaction> {
+        let (attestation_pda, instruction) = self.create_attestation_instruction(
+            self.payer.pubkey(),
+            user,
+            payload,
+            self.expiry_from_now(),
+        )?;
+        let durable_blockhash = self.fetch_nonce(nonce).await?;
+
+        let tx = self
+            .build_transaction(
+                Some(advance_nonce_account(&nonce, &nonce_authority)),
+                vec![instruction],
+                durable_blockhash,
+            )
+            .await?;
+        debug!(%attestation_pda, %nonce, "built durable attestation transaction");
+
+        Ok(tx)
+    }
+}
@@ -0,0 +1,173 @@
+use std::{path::Path, str::FromStr, time::Duration};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use base64::{prelude::BASE64_STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+};
+use tracing::{debug_span, Instrument};
+use zeroize::Zeroizing;
+
+const REMOTE_SIGNER_TIMEOUT: Duration = Duration::from_secs(10);
+const KEYPAIR_LENGTH: usize = 64;
+
+/// Reads a keypair file (a JSON array of 64 bytes, as produced by `solana-keygen`)
+/// without leaving copies of the secret in memory: intermediate buffers are zeroized,
+/// and [`Keypair`] zeroizes its secret on drop.
+pub fn read_keypair(path: impl AsRef<Path>) -> Result<Keypair> {
+    let path = path.as_ref();
+    let contents = Zeroizing::new(std::fs::read_to_string(path)?);
+
+    let mut bytes = Zeroizing::new([0u8; KEYPAIR_LENGTH]);
+    let mut len = 0;
+    for byte in contents
+        .trim()
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .split(',')
+    {
+        let slot = bytes
+            .get_mut(len)
+            .ok_or_else(|| anyhow!("{} has more than {KEYPAIR_LENGTH} bytes", path.display()))?;
+        *slot = byte.trim().parse()?;
+        len += 1;
+    }
+    if len != KEYPAIR_LENGTH {
+        return Err(anyhow!(
+            "{} has {len} bytes instead of {KEYPAIR_LENGTH}",
+            path.display()
+        ));
+    }
+
+    Ok(Keypair::try_from(bytes.as_slice())?)
+}
+
+/// Anything that can sign transaction messages, possibly out of process.
+///
+/// Implemented for every local [`Signer`], e.g. [`Keypair`].
+#[async_trait]
+pub trait TransactionSigner: Send + Sync {
+    fn pubkey(&self) -> Pubkey;
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature>;
+
+    /// Whether it can't sign in this process, see [`OfflineSigner`].
+    fn signs_offline(&self) -> bool {
+        false
+    }
+}
+
+#[async_trait]
+impl<T: Signer + Send + Sync> TransactionSigner for T {
+    fn pubkey(&self) -> Pubkey {
+        Signer::pubkey(self)
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature> {
+        Ok(self.try_sign_message(message)?)
+    }
+}
+
+/// A signer whose key is kept elsewhere and never signs in this process.
+/// Transactions requiring it have to be built unsigned and signed externally,
+/// see [`crate::AttestationService::build_attestation`].
+#[derive(Debug, Clone, Copy)]
+pub struct OfflineSigner(pub Pubkey);
+
+#[async_trait]
+impl TransactionSigner for OfflineSigner {
+    fn pubkey(&self) -> Pubkey {
+        self.0
+    }
+
+    async fn sign_message(&self, _message: &[u8]) -> Result<Signature> {
+        Err(anyhow!("{} signs offline", self.0))
+    }
+
+    fn signs_offline(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Serialize)]
+struct SignRequest {
+    pubkey: String,
+    /// Base64-encoded serialized message.
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct SignResponse {
+    /// Base58-encoded signature.
+    signature: String,
+}
+
+/// Delegates signing to an HTTP signing service (e.g. a KMS/HSM frontend).
+///
+/// Sends `POST {url}` with `{"pubkey": <base58>, "message": <base64>}`
+/// and expects `{"signature": <base58>}` back.
+pub struct RemoteSigner {
+    client: reqwest::Client,
+    url: String,
+    pubkey: Pubkey,
+    token: Option<String>,
+}
+
+impl RemoteSigner {
+    pub fn new(url: impl Into<String>, pubkey: Pubkey) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(REMOTE_SIGNER_TIMEOUT)
+                .build()
+                .expect("static client configuration"),
+            url: url.into(),
+            pubkey,
+            token: None,
+        }
+    }
+
+    /// Authenticates every request with `Authorization: Bearer <token>`.
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+}
+
+#[async_trait]
+impl TransactionSigner for RemoteSigner {
+    fn pubkey(&self) -> Pubkey {
+        self.pubkey
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature> {
+        let span = debug_span!("signer.remote", pubkey = %self.pubkey, url = %self.url);
+
+        let mut request = self.client.post(&self.url).json(&SignRequest {
+            pubkey: self.pubkey.to_string(),
+            message: BASE64_STANDARD.encode(message),
+        });
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let response: SignResponse = request
+            .send()
+            .instrument(span.clone())
+            .await?
+            .error_for_status()?
+            .json()
+            .instrument(span)
+            .await?;
+
+        let signature = Signature::from_str(&response.signature)?;
+        if !signature.verify(self.pubkey.as_ref(), message) {
+            return Err(anyhow!(
+                "remote signer returned an invalid signature for {}",
+                self.pubkey
+            ));
+        }
+        Ok(signature)
+    }
+}
@@ -0,0 +1,190 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use futures_util::{stream::BoxStream, Stream, StreamExt};
+use solana_account_decoder_client_types::{UiAccount, UiAccountEncoding};
+use solana_attestation_service_client::programs::SOLANA_ATTESTATION_SERVICE_ID;
+use solana_client::{
+    nonblocking::pubsub_client::PubsubClient,
+    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+};
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::{mpsc, oneshot};
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{debug, warn};
+
+use crate::{decode_attestation, AttestationAccount, AttestationService};
+
+/// Updates buffered for a slow consumer before the subscription waits for it.
+const UPDATES_BUFFER: usize = 64;
+/// Most accounts `getMultipleAccounts` returns at once.
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+/// A change to an attestation account, see [`AttestationService::subscribe_attestation`].
+#[derive(Debug, Clone)]
+pub enum AttestationUpdate {
+    /// Attestation was created or changed.
+    Attested {
+        slot: u64,
+        attestation: AttestationAccount,
+    },
+    /// Attestation account was closed, i.e. revoked.
+    Closed { slot: u64, pda: Pubkey },
+}
+
+impl AttestationUpdate {
+    pub fn pda(&self) -> Pubkey {
+        match self {
+            Self::Attested { attestation, .. } => attestation.pda,
+            Self::Closed { pda, .. } => *pda,
+        }
+    }
+}
+
+enum Target {
+    Account(Pubkey, RpcAccountInfoConfig),
+    Program(RpcProgramAccountsConfig),
+}
+
+impl AttestationService {
+    /// Updates of the attestation of `user`, via `accountSubscribe`, from creation to revocation.
+    /// The stream ends if the websocket disconnects, so callers resubscribe as they see fit.
+    pub async fn subscribe_attestation(
+        &self,
+        user: Pubkey,
+    ) -> Result<impl Stream<Item = AttestationUpdate>> {
+        let pda = Self::attestation_pda(self.cred_pda, self.schema_pda, user);
+        let config = RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(self.rpc.commitment()),
+            ..RpcAccountInfoConfig::default()
+        };
+        self.subscribe(Target::Account(pda, config)).await
+    }
+
+    /// Updates of every attestation of this credential and schema, via `programSubscribe`.
+    /// Closed accounts don't match the subscription filters, so revocations aren't included.
+    /// The stream ends if the websocket disconnects.
+    pub async fn subscribe_all(&self) -> Result<impl Stream<Item = AttestationUpdate>> {
+        self.subscribe(Target::Program(self.attestation_accounts_config()))
+            .await
+    }
+
+    /// Whether each of `pdas` is an open account, as of the returned slot, bypassing the cache.
+    /// Lets callers of [`Self::subscribe_all`] notice revocations, which it doesn't include.
+    pub async fn attestations_exist(&self, pdas: &[Pubkey]) -> Result<(u64, Vec<bool>)> {
+        let mut slot = 0;
+        let mut exist = Vec::with_capacity(pdas.len());
+        for chunk in pdas.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            let accounts = self
+                .rpc
+                .get_multiple_accounts_with_commitment(chunk, self.rpc.commitment())
+                .await?;
+            slot = slot.max(accounts.context.slot);
+            exist.extend(
+                accounts
+                    .value
+                    .iter()
+                    .map(|account| account.as_ref().is_some_and(|account| account.lamports > 0)),
+            );
+        }
+        Ok((slot, exist))
+    }
+
+    /// Subscribes on a task owning the websocket, returning once the subscription is confirmed.
+    /// Unsubscribes once the stream is dropped.
+    async fn subscribe(&self, target: Target) -> Result<ReceiverStream<AttestationUpdate>> {
+        let ws_url = self.cluster.ws_url().to_string();
+        let (ready_tx, ready_rx) = oneshot::channel::<Result<()>>();
+        let (updates_tx, updates_rx) = mpsc::channel(UPDATES_BUFFER);
+
+        tokio::spawn(async move {
+            let pubsub = match PubsubClient::new(&ws_url).await {
+                Ok(pubsub) => pubsub,
+                Err(err) => {
+                    _ = ready_tx.send(Err(err.into()));
+                    return;
+                }
+            };
+            let subscribed = match target {
+                Target::Account(pda, config) => pubsub
+                    .account_subscribe(&pda, Some(config))
+                    .await
+                    .map(|(notifications, unsubscribe)| {
+                        let notifications = notifications
+                            .map(move |notification| {
+                                (notification.context.slot, Some(pda), notification.value)
+                            })
+                            .boxed();
+                        (notifications as BoxStream<_>, unsubscribe)
+                    }),
+                Target::Program(config) => pubsub
+                    .program_subscribe(&SOLANA_ATTESTATION_SERVICE_ID, Some(config))
+                    .await
+                    .map(|(notifications, unsubscribe)| {
+                        let notifications = notifications
+                            .map(|notification| {
+                                let pda = Pubkey::from_str(&notification.value.pubkey).ok();
+                                (notification.context.slot, pda, notification.value.account)
+                            })
+                            .boxed();
+                        (notifications as BoxStream<_>, unsubscribe)
+                    }),
+            };
+            let (mut notifications, unsubscribe) = match subscribed {
+                Ok(subscribed) => subscribed,
+                Err(err) => {
+                    _ = ready_tx.send(Err(err.into()));
+                    return;
+                }
+            };
+            _ = ready_tx.send(Ok(()));
+
+            loop {
+                let notification = tokio::select! {
+                    notification = notifications.next() => notification,
+                    _ = updates_tx.closed() => break,
+                };
+                let Some((slot, pda, account)) = notification else {
+                    debug!("attestation subscription closed");
+                    break;
+                };
+                let Some(pda) = pda else {
+                    warn!("unexpected pubkey in attestation notification");
+                    continue;
+                };
+                match decode_update(slot, pda, &account) {
+                    Ok(update) => {
+                        if updates_tx.send(update).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => warn!(%pda, %err, "couldn't decode attestation update"),
+                }
+            }
+            drop(notifications);
+            unsubscribe().await;
+        });
+
+        ready_rx
+            .await
+            .map_err(|_| anyhow!("attestation subscription task stopped"))??;
+        Ok(ReceiverStream::new(updates_rx))
+    }
+}
+
+/// Closed accounts are notified as empty and owned by the system program.
+fn decode_update(slot: u64, pda: Pubkey, account: &UiAccount) -> Result<AttestationUpdate> {
+    let data = account
+        .data
+        .decode()
+        .ok_or_else(|| anyhow!("account data isn't base64"))?;
+    if account.lamports == 0 || data.is_empty() {
+        return Ok(AttestationUpdate::Closed { slot, pda });
+    }
+    if account.owner != SOLANA_ATTESTATION_SERVICE_ID.to_string() {
+        return Err(anyhow!("account is owned by {}", account.owner));
+    }
+    let attestation = decode_attestation(pda, &data)?;
+    Ok(AttestationUpdate::Attested { slot, attestation })
+}
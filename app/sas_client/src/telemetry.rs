@@ -0,0 +1,117 @@
+//! Metrics recorded through the [`metrics`] facade.
+//! Nothing is exported until the application installs a recorder,
+//! e.g. `metrics-exporter-prometheus`.
+
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use metrics::{counter, describe_counter, describe_gauge, describe_histogram, histogram, Unit};
+use solana_client::{
+    client_error::{ClientError, ClientErrorKind, Result as ClientResult},
+    nonblocking::rpc_client::RpcClient,
+    rpc_request::RpcRequest,
+    rpc_sender::{RpcSender, RpcTransportStats},
+};
+use solana_rpc_client::{http_sender::HttpSender, rpc_client::RpcClientConfig};
+use solana_sdk::commitment_config::CommitmentConfig;
+use tracing::debug;
+
+pub const RPC_REQUEST_DURATION: &str = "sas_rpc_request_duration_seconds";
+pub const RPC_RETRIES: &str = "sas_rpc_retries_total";
+pub const CONFIRMATION_DURATION: &str = "sas_transaction_confirmation_duration_seconds";
+pub const TRANSACTIONS: &str = "sas_transactions_total";
+pub const ATTESTATIONS_CREATED: &str = "sas_attestations_created_total";
+pub const PAYER_BALANCE: &str = "sas_payer_balance_lamports";
+pub const CACHE_REQUESTS: &str = "sas_attestation_cache_requests_total";
+pub const CACHE_INVALIDATIONS: &str = "sas_attestation_cache_invalidations_total";
+
+const MAX_RPC_RETRIES: u32 = 3;
+const RPC_RETRY_DELAY: Duration = Duration::from_millis(250);
+
+/// Registers descriptions and units of every metric above with the installed recorder.
+pub fn describe_metrics() {
+    describe_histogram!(
+        RPC_REQUEST_DURATION,
+        Unit::Seconds,
+        "RPC request latency by method and result"
+    );
+    describe_counter!(
+        RPC_RETRIES,
+        "RPC requests retried after connection failures or timeouts, by method"
+    );
+    describe_histogram!(
+        CONFIRMATION_DURATION,
+        Unit::Seconds,
+        "Time from sending a transaction to reaching the desired commitment"
+    );
+    describe_counter!(TRANSACTIONS, "Sent transactions by outcome");
+    describe_counter!(ATTESTATIONS_CREATED, "Attestations created on-chain");
+    describe_gauge!(PAYER_BALANCE, "Last seen payer balance, in lamports");
+    describe_counter!(
+        CACHE_REQUESTS,
+        "Attestation cache lookups by result: hit or miss"
+    );
+    describe_counter!(
+        CACHE_INVALIDATIONS,
+        "Attestations dropped from cache, by reason: exists, revoked, created, subscription or resubscribe"
+    );
+}
+
+/// RPC client whose requests are timed per method, see [`MeteredSender`].
+pub(crate) fn metered_rpc_client(url: &str, commitment: CommitmentConfig) -> RpcClient {
+    RpcClient::new_sender(
+        MeteredSender(HttpSender::new(url)),
+        RpcClientConfig::with_commitment(commitment),
+    )
+}
+
+/// [`HttpSender`] that records [`RPC_REQUEST_DURATION`] and retries requests that never
+/// got a response, see [`is_retryable`].
+pub(crate) struct MeteredSender(HttpSender);
+
+#[async_trait]
+impl RpcSender for MeteredSender {
+    async fn send(
+        &self,
+        request: RpcRequest,
+        params: serde_json::Value,
+    ) -> ClientResult<serde_json::Value> {
+        let method = request.to_string();
+        let mut retries = 0;
+        loop {
+            let started = Instant::now();
+            let result = self.0.send(request, params.clone()).await;
+            let outcome = if result.is_ok() { "ok" } else { "error" };
+            histogram!(RPC_REQUEST_DURATION, "method" => method.clone(), "result" => outcome)
+                .record(started.elapsed());
+
+            match result {
+                Err(err) if retries < MAX_RPC_RETRIES && is_retryable(request, &err) => {
+                    retries += 1;
+                    counter!(RPC_RETRIES, "method" => method.clone()).increment(1);
+                    debug!(%method, %err, retries, "retrying RPC request");
+                    tokio::time::sleep(RPC_RETRY_DELAY * retries).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        self.0.get_transport_stats()
+    }
+
+    fn url(&self) -> String {
+        self.0.url()
+    }
+}
+
+/// Requests that couldn't connect never reached the node. Timed out ones may have been processed,
+/// which is harmless for reads, and for `sendTransaction` as a signed transaction lands once,
+/// but `requestAirdrop` would fund twice.
+fn is_retryable(request: RpcRequest, err: &ClientError) -> bool {
+    let ClientErrorKind::Reqwest(err) = &err.kind else {
+        return false;
+    };
+    err.is_connect() || (err.is_timeout() && !matches!(request, RpcRequest::RequestAirdrop))
+}
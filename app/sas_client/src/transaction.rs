@@ -0,0 +1,54 @@
+use anyhow::{anyhow, Result};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use solana_sdk::{pubkey::Pubkey, signature::Signature, transaction::VersionedTransaction};
+
+use crate::TransactionSigner;
+
+/// Adds signatures of `signers` to `tx`, leaving the rest of required signatures as they are.
+pub async fn partial_sign(
+    tx: &mut VersionedTransaction,
+    signers: &[&dyn TransactionSigner],
+) -> Result<()> {
+    let required = tx.message.header().num_required_signatures as usize;
+    tx.signatures.resize(required, Signature::default());
+
+    let data = tx.message.serialize();
+    let keys = &tx.message.static_account_keys()[..required];
+    for signer in signers {
+        let pubkey = signer.pubkey();
+        let position = keys
+            .iter()
+            .position(|key| *key == pubkey)
+            .ok_or_else(|| anyhow!("{pubkey} isn't a required signer"))?;
+        // Payer often doubles as issuer or signer, no need to sign twice.
+        if tx.signatures[position] == Signature::default() {
+            tx.signatures[position] = signer.sign_message(&data).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Required signers that haven't signed `tx` yet.
+pub fn missing_signers(tx: &VersionedTransaction) -> Vec<Pubkey> {
+    let required = tx.message.header().num_required_signatures as usize;
+    tx.message.static_account_keys()[..required]
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| {
+            tx.signatures
+                .get(*i)
+                .is_none_or(|sig| *sig == Signature::default())
+        })
+        .map(|(_, key)| *key)
+        .collect()
+}
+
+/// Base64-encoded wire format, the same one `sendTransaction` accepts.
+pub fn encode_transaction(tx: &VersionedTransaction) -> Result<String> {
+    Ok(BASE64_STANDARD.encode(bincode::serialize(tx)?))
+}
+
+pub fn decode_transaction(encoded: &str) -> Result<VersionedTransaction> {
+    let bytes = BASE64_STANDARD.decode(encoded)?;
+    Ok(bincode::deserialize(&bytes)?)
+}
@@ -1,2 +1,10 @@
 #[cfg(test)]
+mod test_cluster;
+#[cfg(test)]
+mod test_config;
+#[cfg(test)]
 mod test_sas;
+#[cfg(test)]
+mod test_signer;
+#[cfg(test)]
+mod test_transaction;
@@ -0,0 +1,27 @@
+use sas_client::Cluster;
+
+#[test]
+fn test_cluster_names() {
+    assert_eq!("devnet".parse::<Cluster>().unwrap(), Cluster::Devnet);
+    assert_eq!("mainnet-beta".parse::<Cluster>().unwrap(), Cluster::Mainnet);
+    assert!("mainnet-alpha".parse::<Cluster>().is_err());
+
+    assert!(Cluster::Localnet.is_test_cluster());
+    assert!(!Cluster::Mainnet.is_test_cluster());
+}
+
+#[test]
+fn test_custom_cluster_urls() {
+    let local = "http://127.0.0.1:8899".parse::<Cluster>().unwrap();
+    assert_eq!(local.rpc_url(), "http://127.0.0.1:8899");
+    assert_eq!(local.ws_url(), "ws://127.0.0.1:8900/");
+    // There's no telling whether custom endpoints are mainnet, so they are treated as such.
+    assert!(!local.is_test_cluster());
+
+    let provider = "https://rpc.example.com/?api-key=1"
+        .parse::<Cluster>()
+        .unwrap();
+    assert_eq!(provider.ws_url(), "wss://rpc.example.com/?api-key=1");
+
+    assert!("ftp://127.0.0.1".parse::<Cluster>().is_err());
+}
@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+
+use anchor_client::solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer};
+use sas_client::{AttestationService, ServiceConfig, SignerConfig};
+
+/// The devnet `.env` of earlier versions, with the same keypair for payer, issuer and signer:
+/// overriding the endpoints keeps devnet a test cluster, so that the keys are allowed.
+#[test]
+fn test_devnet_env_with_rpc_url() {
+    let keypair = Keypair::new();
+    let bytes: Vec<String> = keypair.to_bytes().iter().map(u8::to_string).collect();
+    let path = std::env::temp_dir().join(format!("devnet-{}.json", std::process::id()));
+    std::fs::write(&path, format!("[{}]", bytes.join(","))).unwrap();
+
+    let creds = path.to_str().unwrap();
+    let vars = HashMap::from([
+        ("CLUSTER", "devnet"),
+        ("RPC_URL", "https://api.devnet.solana.com"),
+        ("PAYER_CREDS", creds),
+        ("ISSUER_CREDS", creds),
+        ("SIGNER_CREDS", creds),
+    ]);
+    let mut config = ServiceConfig::default();
+    config
+        .apply_vars(|name| vars.get(name).map(|value| value.to_string()))
+        .unwrap();
+
+    let cluster = config.cluster().unwrap();
+    assert!(cluster.is_test_cluster());
+    assert_eq!(cluster.rpc_url(), "https://api.devnet.solana.com");
+    assert_eq!(cluster.ws_url(), "wss://api.devnet.solana.com/");
+
+    let service = AttestationService::from_config(&config).unwrap();
+    assert!(service.cluster().is_test_cluster());
+    assert_eq!(service.payer().pubkey(), keypair.pubkey());
+    std::fs::remove_file(path).unwrap();
+
+    config.cluster = "mainnet".to_string();
+    assert!(!config.cluster().unwrap().is_test_cluster());
+}
+
+#[test]
+fn test_signer_config() {
+    let pubkey = Pubkey::new_unique();
+    let offline = SignerConfig {
+        pubkey: Some(pubkey.to_string()),
+        ..SignerConfig::default()
+    };
+    let signer = offline.load("signer").unwrap();
+    assert_eq!(signer.pubkey(), pubkey);
+    assert!(signer.signs_offline());
+
+    let remote = SignerConfig {
+        url: Some("http://127.0.0.1:8080/sign".to_string()),
+        ..offline.clone()
+    };
+    assert!(!remote.load("signer").unwrap().signs_offline());
+
+    let remote_without_pubkey = SignerConfig {
+        url: remote.url.clone(),
+        ..SignerConfig::default()
+    };
+    assert!(remote_without_pubkey.load("signer").is_err());
+    assert!(SignerConfig::default().load("signer").is_err());
+}
@@ -1,4 +1,4 @@
-use std::ops::Deref;
+use std::{ops::Deref, sync::Arc};
 
 use anchor_client::{
     solana_sdk::{
@@ -15,19 +15,25 @@ use anchor_lang::{
     solana_program::{self},
     InstructionData, ToAccountMetas,
 };
-use sas_client::{AttestationPayload, AttestationService};
+use sas_client::{
+    AttestationCacheConfig, AttestationExists, AttestationPayload, AttestationService,
+    Cluster as SasCluster,
+};
 
 use test_solana_program::accounts::Validate as ValidateAccounts;
 use test_solana_program::instruction::Validate as ValidateIx;
 
-async fn init_sas() -> AttestationService {
+fn new_sas() -> AttestationService {
     let anchor_wallet = std::env::var("ANCHOR_WALLET").unwrap();
 
-    let payer = read_keypair_file(&anchor_wallet).unwrap();
-    let issuer = payer.insecure_clone();
-    let signer = payer.insecure_clone();
-    let mut service = AttestationService::new("http://127.0.0.1:8899", payer, issuer, signer);
+    let payer = Arc::new(read_keypair_file(&anchor_wallet).unwrap());
+    let issuer = Arc::new(payer.insecure_clone());
+    let signer = Arc::new(payer.insecure_clone());
+    AttestationService::new(SasCluster::Localnet, payer, issuer, signer)
+}
 
+async fn init_sas() -> AttestationService {
+    let mut service = new_sas();
     service.init_unchecked().await.unwrap();
     service
 }
@@ -61,9 +67,11 @@ async fn call_validate<C: Deref<Target = impl Signer> + Clone>(
 async fn test_attestation() {
     let service = init_sas().await;
 
-    let anchor_wallet = std::env::var("ANCHOR_WALLET").unwrap();
-    let payer = read_keypair_file(&anchor_wallet).unwrap();
-    let client = Client::new_with_options(Cluster::Localnet, &payer, CommitmentConfig::confirmed());
+    let client = Client::new_with_options(
+        Cluster::Localnet,
+        service.payer(),
+        CommitmentConfig::confirmed(),
+    );
 
     let program = client.program(test_solana_program::ID).unwrap();
 
@@ -90,6 +98,22 @@ async fn test_attestation() {
         res_ok
     );
 
+    // Case A2: attesting the same user again -> AttestationExists
+    let err = service
+        .create_attestation(
+            user_ok,
+            AttestationPayload {
+                age: true,
+                country: true,
+            },
+        )
+        .await
+        .expect_err("attesting user_ok twice should fail");
+    let exists = err
+        .downcast_ref::<AttestationExists>()
+        .expect("second attestation should fail with AttestationExists");
+    assert_eq!(exists.pda, att_ok);
+
     // Case B: user without any attestation -> runtime should fail (account not found)
     let user_missing = Pubkey::new_unique();
     let att_missing = AttestationService::attestation_pda(cred_pda, scheme_pda, user_missing);
@@ -121,4 +145,77 @@ async fn test_attestation() {
         res_wrong_owner.is_err(),
         "validate should fail if attestation account is not owned by SAS program"
     );
+
+    // Case A3: renewing closes and re-creates in one transaction -> fresh expiry, and nothing
+    // happens if there's no attestation to close
+    let before = service
+        .fetch_attestation_account(user_ok)
+        .await
+        .unwrap()
+        .unwrap();
+    let renewed = service
+        .renew_attestation(
+            user_ok,
+            AttestationPayload {
+                age: true,
+                country: true,
+            },
+        )
+        .await
+        .expect("failed to renew attestation for user_ok");
+    assert_eq!(renewed.pda, att_ok);
+    assert!(renewed.expiry >= before.expiry);
+    let after = service
+        .fetch_attestation_account(user_ok)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(after.expiry, renewed.expiry);
+    let user_unattested = Pubkey::new_unique();
+    assert!(service
+        .renew_attestation(
+            user_unattested,
+            AttestationPayload {
+                age: true,
+                country: true,
+            },
+        )
+        .await
+        .is_err());
+    assert!(service
+        .fetch_attestation(user_unattested)
+        .await
+        .unwrap()
+        .is_none());
+
+    // Case E: attestation revoked by another process, e.g. a CLI command, while a server has it
+    // cached -> no longer listed, and validate fails right away
+    let listed = |accounts: &[sas_client::AttestationAccount]| {
+        accounts.iter().any(|account| account.user == user_ok)
+    };
+    assert!(listed(&service.list_attestations().await.unwrap()));
+    // Credential and schema exist already, so the server only checks for them.
+    let mut server = new_sas().with_attestation_cache(AttestationCacheConfig::default());
+    server.init().await.unwrap();
+    assert!(server.fetch_attestation(user_ok).await.unwrap().is_some());
+    let revoked = service
+        .revoke_attestation(user_ok)
+        .await
+        .expect("failed to revoke attestation for user_ok");
+    assert_eq!(revoked.pda, att_ok);
+    assert!(
+        !listed(&service.list_attestations().await.unwrap()),
+        "revoked attestation should not be listed"
+    );
+    // The server didn't hear about the revocation, so lookups are served from its cache...
+    assert!(
+        server.fetch_attestation(user_ok).await.unwrap().is_some(),
+        "the second lookup should be served from cache"
+    );
+    // ...but its /validate asks the program, which rejects the revoked attestation.
+    let res_revoked = call_validate(&program, att_ok, cred_pda, scheme_pda, user_ok).await;
+    assert!(
+        res_revoked.is_err(),
+        "validate should fail for a revoked attestation"
+    );
 }
@@ -0,0 +1,62 @@
+use std::path::PathBuf;
+
+use anchor_client::solana_sdk::{signature::Keypair, signer::Signer};
+use sas_client::read_keypair;
+
+/// Writes `contents` to a file unique to this test run.
+fn keypair_file(name: &str, contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("{name}-{}.json", std::process::id()));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+fn json_bytes(bytes: &[u8]) -> String {
+    let bytes: Vec<String> = bytes.iter().map(u8::to_string).collect();
+    format!("[{}]", bytes.join(","))
+}
+
+#[test]
+fn test_read_keypair() {
+    let keypair = Keypair::new();
+    // `solana-keygen` output, plus whitespace it may be edited into.
+    let path = keypair_file(
+        "valid",
+        &format!(" {}\n", json_bytes(&keypair.to_bytes()).replace(',', ", ")),
+    );
+    assert_eq!(read_keypair(&path).unwrap().pubkey(), keypair.pubkey());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_read_keypair_wrong_length() {
+    let bytes = Keypair::new().to_bytes();
+    for (name, bytes) in [
+        ("short", &bytes[..63]),
+        ("long", &[&bytes[..], &[0]].concat()),
+    ] {
+        let path = keypair_file(name, &json_bytes(bytes));
+        let err = read_keypair(&path).unwrap_err().to_string();
+        assert!(err.contains("bytes"), "{name}: {err}");
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn test_read_keypair_malformed() {
+    let mut bytes: Vec<String> = Keypair::new()
+        .to_bytes()
+        .iter()
+        .map(u8::to_string)
+        .collect();
+    bytes[10] = "256".to_string();
+    for (name, contents) in [
+        ("out-of-range", format!("[{}]", bytes.join(","))),
+        ("not-json", "not a keypair".to_string()),
+        ("empty", String::new()),
+    ] {
+        let path = keypair_file(name, &contents);
+        assert!(read_keypair(&path).is_err(), "{name}");
+        std::fs::remove_file(path).unwrap();
+    }
+    assert!(read_keypair("/nonexistent/keypair.json").is_err());
+}
@@ -0,0 +1,64 @@
+use anchor_client::solana_sdk::{
+    hash::Hash,
+    instruction::{AccountMeta, Instruction},
+    message::{Message, VersionedMessage},
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+    transaction::VersionedTransaction,
+};
+use sas_client::transaction::{
+    decode_transaction, encode_transaction, missing_signers, partial_sign,
+};
+
+/// An instruction signed by `from` and paid by `payer`, so that both have to sign.
+fn unsigned_transaction(payer: &Pubkey, from: &Pubkey) -> VersionedTransaction {
+    let ix = Instruction::new_with_bytes(
+        Pubkey::new_unique(),
+        &[],
+        vec![AccountMeta::new(*from, true)],
+    );
+    let message = Message::new_with_blockhash(&[ix], Some(payer), &Hash::new_unique());
+    VersionedTransaction {
+        signatures: vec![],
+        message: VersionedMessage::Legacy(message),
+    }
+}
+
+#[tokio::test]
+async fn test_partial_sign_leaves_missing_signer() {
+    let payer = Keypair::new();
+    let user = Keypair::new();
+    let mut tx = unsigned_transaction(&payer.pubkey(), &user.pubkey());
+    assert_eq!(missing_signers(&tx), vec![payer.pubkey(), user.pubkey()]);
+
+    partial_sign(&mut tx, &[&payer]).await.unwrap();
+    assert_eq!(missing_signers(&tx), vec![user.pubkey()]);
+    assert_eq!(tx.signatures[1], Signature::default());
+    assert!(tx.signatures[0].verify(payer.pubkey().as_ref(), &tx.message.serialize()));
+
+    // Signing again keeps the existing signature.
+    let signature = tx.signatures[0];
+    partial_sign(&mut tx, &[&payer, &user]).await.unwrap();
+    assert_eq!(tx.signatures[0], signature);
+    assert!(missing_signers(&tx).is_empty());
+    assert!(tx.verify_with_results().into_iter().all(|ok| ok));
+
+    let stranger = Keypair::new();
+    assert!(partial_sign(&mut tx, &[&stranger]).await.is_err());
+}
+
+#[tokio::test]
+async fn test_transaction_encoding_round_trip() {
+    let payer = Keypair::new();
+    let user = Keypair::new();
+    let mut tx = unsigned_transaction(&payer.pubkey(), &user.pubkey());
+    partial_sign(&mut tx, &[&payer]).await.unwrap();
+
+    let decoded = decode_transaction(&encode_transaction(&tx).unwrap()).unwrap();
+    assert_eq!(decoded, tx);
+    assert_eq!(missing_signers(&decoded), vec![user.pubkey()]);
+
+    assert!(decode_transaction("not base64!").is_err());
+    assert!(decode_transaction("AAAA").is_err());
+}